@@ -0,0 +1,108 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{seq::SliceRandom, SeedableRng};
+use ultimate_tic_tac_toe::{render_board, Board, DrawPolicy, Game, Placement, Rules, BOARD_SIZE};
+
+/// A game with `num_layers` layers after `num_moves` random moves, the same every time
+fn random_game(num_layers: usize, num_moves: usize) -> Game {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    let mut game = Game::new(num_layers, Rules::default());
+    for _ in 0..num_moves {
+        let Some(mv) = game.legal_moves().choose(&mut rng).cloned() else {
+            break;
+        };
+        game.play(mv).unwrap();
+    }
+    game
+}
+
+fn board_results(c: &mut Criterion) {
+    let mut group = c.benchmark_group("board_results");
+    for num_layers in [2, 3] {
+        let board = random_game(num_layers, 9usize.pow(num_layers as u32) / 3)
+            .board()
+            .clone();
+        group.bench_with_input(
+            BenchmarkId::new("get_winner", num_layers),
+            &board,
+            |b, board| b.iter(|| black_box(board).get_winner()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("is_stalemate", num_layers),
+            &board,
+            |b, board| b.iter(|| black_box(board).is_stalemate()),
+        );
+    }
+    group.finish();
+}
+
+/// Stands in for building a new board in the app, which needs a GUI context, its restart builds the board with
+/// [`Game::new`] and nothing else
+fn new_board(c: &mut Criterion) {
+    let mut group = c.benchmark_group("new_board");
+    for num_layers in 1..=4 {
+        group.bench_with_input(
+            BenchmarkId::new("Board::new", num_layers),
+            &num_layers,
+            |b, &num_layers| b.iter(|| Board::new(black_box(num_layers))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("Game::new", num_layers),
+            &num_layers,
+            |b, &num_layers| b.iter(|| Game::new(black_box(num_layers), Rules::default())),
+        );
+    }
+    group.finish();
+}
+
+fn move_generation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("move_generation");
+    for num_layers in [2, 3] {
+        let game = random_game(num_layers, 9usize.pow(num_layers as u32) / 3);
+        group.bench_with_input(
+            BenchmarkId::new("legal_moves", num_layers),
+            &game,
+            |b, game| b.iter(|| black_box(game).legal_moves()),
+        );
+    }
+    let mut game = Game::new(
+        2,
+        Rules {
+            placement: Placement::SentTo,
+            ..Default::default()
+        },
+    );
+    group.bench_function("perft/3", |b| b.iter(|| game.perft(black_box(3))));
+    group.finish();
+}
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render_board");
+    for num_layers in [2, 3] {
+        let board = random_game(num_layers, 9usize.pow(num_layers as u32) / 3)
+            .board()
+            .clone();
+        let mut per_object_data = vec![];
+        group.bench_with_input(
+            BenchmarkId::new("instances", num_layers),
+            &board,
+            |b, board| {
+                b.iter(|| {
+                    per_object_data.clear();
+                    render_board(
+                        black_box(board),
+                        (0.0, 0.0).into(),
+                        cgmath::vec2(BOARD_SIZE, BOARD_SIZE) / board.size() as f32,
+                        None,
+                        DrawPolicy::default(),
+                        &mut per_object_data,
+                    );
+                    per_object_data.len()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, board_results, new_board, move_generation, render);
+criterion_main!(benches);
@@ -0,0 +1,32 @@
+//! Runs one of the built in bots as an engine, talking the engine protocol on stdin and stdout
+
+use ultimate_tic_tac_toe::{run_engine, Bot, MctsBot, MinimaxBot};
+
+const USAGE: &str = "usage: engine [minimax|mcts]";
+
+fn main() {
+    let mut bot: Box<dyn Bot> = match std::env::args().nth(1).as_deref() {
+        None | Some("minimax") => Box::new(MinimaxBot {
+            max_depth: 12,
+            ..Default::default()
+        }),
+        Some("mcts") => {
+            let mut bot = MctsBot::default();
+            // the search is limited by the move time sent by the host
+            bot.iterations = usize::MAX;
+            Box::new(bot)
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    if let Err(error) = run_engine(
+        bot.as_mut(),
+        std::io::stdin().lock(),
+        std::io::stdout().lock(),
+    ) {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,32 @@
+//! Hosts games for any number of players and spectators, see the `server` module for the protocol
+
+use std::net::TcpListener;
+
+const USAGE: &str = "usage: server [--port <port>]";
+
+fn main() {
+    let mut port = 7879;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next().map(|port| port.parse())) {
+            ("--port", Some(Ok(value))) => port = value,
+            _ => {
+                eprintln!("{USAGE}");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let listener = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("failed to listen on port {port}: {error}");
+            std::process::exit(1);
+        }
+    };
+    println!("Listening on port {port}");
+    if let Err(error) = ultimate_tic_tac_toe::serve(listener) {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,226 @@
+use std::{io::Write, time::Duration};
+
+use ultimate_tic_tac_toe::{
+    play_game, BoardShape, Bot, DrawPolicy, Game, MatchResults, Misere, Placement, PlayerKind,
+    PlayerSettings, Rules, State,
+};
+
+const USAGE: &str = "\
+usage: tournament [--games <n>] [--layers <n>] [--placement <free|sent-to>]
+                  [--draws <dead|both|majority>] [--boards <size>:<in a row>,...] [--misere <off|top|all>]
+                  [--movetime <ms>] [--moves-file <path>] <player> <player>
+
+players:
+  random[:<seed>]        plays random moves
+  greedy[:<seed>]        wins a board if it can, otherwise plays random moves
+  minimax[:<depth>]      alpha-beta search
+  mcts[:<iterations>]    Monte Carlo tree search
+  engine:<command>       an external program talking the engine protocol
+
+The players alternate who starts, results are from the point of view of the first player";
+
+struct Args {
+    games: u32,
+    num_layers: usize,
+    rules: Rules,
+    movetime: Option<Duration>,
+    moves_file: Option<String>,
+    players: [PlayerSettings; 2],
+}
+
+fn parse_player(spec: &str, index: usize) -> Result<PlayerSettings, String> {
+    let (kind, option) = match spec.split_once(':') {
+        Some((kind, option)) => (kind, Some(option)),
+        None => (spec, None),
+    };
+    let mut player = PlayerSettings {
+        kind: kind.parse().map_err(|error| format!("{error}"))?,
+        seed: index as u64,
+        ..Default::default()
+    };
+    let invalid = || format!("invalid option for {kind}: '{}'", option.unwrap_or(""));
+    let number = || option.map(|option| option.parse::<u64>().map_err(|_| invalid()));
+    match player.kind {
+        PlayerKind::Human => return Err("a tournament needs computer players".into()),
+        PlayerKind::Random | PlayerKind::Greedy => {
+            if let Some(seed) = number() {
+                player.seed = seed?;
+            }
+        }
+        PlayerKind::Minimax => {
+            if let Some(depth) = number() {
+                player.minimax.max_depth = depth? as usize;
+            }
+        }
+        PlayerKind::Mcts => {
+            if let Some(iterations) = number() {
+                player.mcts.iterations = iterations? as usize;
+            }
+        }
+        PlayerKind::Engine => player.engine_command = option.ok_or_else(invalid)?.to_string(),
+    }
+    Ok(player)
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        games: 100,
+        num_layers: 2,
+        rules: Rules::default(),
+        movetime: None,
+        moves_file: None,
+        players: Default::default(),
+    };
+    let mut players = vec![];
+
+    let mut arguments = std::env::args().skip(1);
+    while let Some(arg) = arguments.next() {
+        let mut value = || {
+            arguments
+                .next()
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--games" => {
+                args.games = match value()?.parse() {
+                    Ok(games @ 1..) => games,
+                    _ => return Err("the number of games must be at least 1".into()),
+                }
+            }
+            "--layers" => {
+                args.num_layers = match value()?.parse() {
+                    Ok(layers @ 1..) => layers,
+                    _ => return Err("the number of layers must be at least 1".into()),
+                }
+            }
+            "--placement" => {
+                args.rules.placement = value()?
+                    .parse::<Placement>()
+                    .map_err(|error| error.to_string())?
+            }
+            "--draws" => {
+                args.rules.draw_policy = value()?
+                    .parse::<DrawPolicy>()
+                    .map_err(|error| error.to_string())?
+            }
+            "--misere" => {
+                args.rules.misere = value()?
+                    .parse::<Misere>()
+                    .map_err(|error| error.to_string())?
+            }
+            "--boards" => {
+                args.rules.shapes = value()?
+                    .split(',')
+                    .map(str::parse::<BoardShape>)
+                    .collect::<Result<_, _>>()
+                    .map_err(|error| error.to_string())?
+            }
+            "--movetime" => {
+                let millis = value()?
+                    .parse()
+                    .map_err(|_| "invalid move time".to_string())?;
+                args.movetime = Some(Duration::from_millis(millis));
+            }
+            "--moves-file" => args.moves_file = Some(value()?),
+            "--help" | "-h" => return Err(USAGE.into()),
+            spec if !spec.starts_with("--") => players.push(parse_player(spec, players.len())?),
+            _ => return Err(format!("unknown argument '{arg}'\n{USAGE}")),
+        }
+    }
+    args.players = players
+        .try_into()
+        .map_err(|_| format!("expected two players\n{USAGE}"))?;
+    Ok(args)
+}
+
+fn make_bot(player: &PlayerSettings, movetime: Option<Duration>) -> Box<dyn Bot> {
+    let mut bot = match player.make_bot() {
+        Ok(bot) => bot.expect("human players are rejected when parsing"),
+        Err(error) => {
+            eprintln!("failed to start the engine: {error}");
+            std::process::exit(1);
+        }
+    };
+    if let Some(movetime) = movetime {
+        bot.set_time_limit(movetime);
+    }
+    bot
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+    };
+    let mut bots = args
+        .players
+        .each_ref()
+        .map(|player| make_bot(player, args.movetime));
+    let names = bots.each_ref().map(|bot| bot.name());
+    println!("{} vs {}", names[0], names[1]);
+
+    let mut moves_file = args
+        .moves_file
+        .map(|path| match std::fs::File::create(&path) {
+            Ok(file) => std::io::BufWriter::new(file),
+            Err(error) => {
+                eprintln!("failed to create {path}: {error}");
+                std::process::exit(1);
+            }
+        });
+
+    let mut results = MatchResults::default();
+    for i in 0..args.games {
+        let mut game = Game::new(args.num_layers, args.rules.clone());
+        let [first, second] = &mut bots;
+        // the first player starts every other game
+        let (side, winner) = if i % 2 == 0 {
+            (
+                State::Circle,
+                play_game(&mut game, [first.as_mut(), second.as_mut()]),
+            )
+        } else {
+            (
+                State::Cross,
+                play_game(&mut game, [second.as_mut(), first.as_mut()]),
+            )
+        };
+        results.add(winner, side);
+
+        let result = match winner {
+            Some(winner) => format!("{winner} won"),
+            None => "draw".into(),
+        };
+        let (circle, cross) = match side {
+            State::Circle => (&names[0], &names[1]),
+            State::Cross => (&names[1], &names[0]),
+        };
+        println!(
+            "game {}: {circle} (O) vs {cross} (X), {result} after {} moves",
+            i + 1,
+            game.num_moves()
+        );
+        if let Some(file) = &mut moves_file {
+            let written = writeln!(
+                file,
+                "# game {}: {circle} (O) vs {cross} (X), {result}\n{}",
+                i + 1,
+                game.to_save_string(false)
+            );
+            if let Err(error) = written {
+                eprintln!("failed to write the moves: {error}");
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(Err(error)) = moves_file.map(|mut file| file.flush()) {
+        eprintln!("failed to write the moves: {error}");
+        std::process::exit(1);
+    }
+
+    println!();
+    println!("{results}");
+}
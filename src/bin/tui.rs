@@ -0,0 +1,205 @@
+use std::io::{BufRead, Write};
+
+use ultimate_tic_tac_toe::{
+    BoardShape, CellPath, DrawPolicy, Game, Misere, Move, Outcome, Placement, PlayerKind,
+    PlayerSettings, Rules, State,
+};
+
+const USAGE: &str = "\
+usage: tui [--layers <n>] [--placement <free|sent-to>] [--draws <dead|both|majority>]
+           [--boards <size>:<in a row>,...] [--misere <off|top|all>]
+           [--circle <player>] [--cross <player>]
+           [--circle-engine <command>] [--cross-engine <command>]
+
+players: human, random, greedy, minimax, mcts, engine";
+
+const HELP: &str = "\
+Enter a move as a cell path from the outermost board, either as cell indices
+separated by dots (index = x + 3 * y, 0 is the bottom left cell, e.g. 4.0)
+or as column letters and row numbers separated by slashes (e.g. b2/a1).
+Boards that aren't three by three only take letters and numbers.
+
+commands:
+  undo      take back the last move
+  redo      play the last move that was taken back
+  moves     list the moves played so far
+  legal     list the legal moves
+  position  print the position notation
+  help      show this message
+  quit      exit";
+
+fn parse_args() -> Result<(usize, Rules, [PlayerSettings; 2]), String> {
+    let mut num_layers = 2;
+    let mut rules = Rules::default();
+    let mut players: [PlayerSettings; 2] = Default::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--layers" => {
+                num_layers = match value()?.parse() {
+                    Ok(layers @ 1..) => layers,
+                    _ => return Err("the number of layers must be at least 1".into()),
+                }
+            }
+            "--placement" => {
+                rules.placement = value()?
+                    .parse::<Placement>()
+                    .map_err(|error| error.to_string())?
+            }
+            "--draws" => {
+                rules.draw_policy = value()?
+                    .parse::<DrawPolicy>()
+                    .map_err(|error| error.to_string())?
+            }
+            "--misere" => {
+                rules.misere = value()?
+                    .parse::<Misere>()
+                    .map_err(|error| error.to_string())?
+            }
+            "--boards" => {
+                rules.shapes = value()?
+                    .split(',')
+                    .map(str::parse::<BoardShape>)
+                    .collect::<Result<_, _>>()
+                    .map_err(|error| error.to_string())?
+            }
+            "--circle" => {
+                players[0].kind = value()?
+                    .parse::<PlayerKind>()
+                    .map_err(|error| error.to_string())?
+            }
+            "--cross" => {
+                players[1].kind = value()?
+                    .parse::<PlayerKind>()
+                    .map_err(|error| error.to_string())?
+            }
+            "--circle-engine" => {
+                players[0].kind = PlayerKind::Engine;
+                players[0].engine_command = value()?;
+            }
+            "--cross-engine" => {
+                players[1].kind = PlayerKind::Engine;
+                players[1].engine_command = value()?;
+            }
+            "--help" | "-h" => return Err(USAGE.into()),
+            _ => return Err(format!("unknown argument '{arg}'\n{USAGE}")),
+        }
+    }
+    Ok((num_layers, rules, players))
+}
+
+fn print_game(game: &Game) {
+    println!();
+    print!("{}", game.board().to_text());
+    match game.status() {
+        Outcome::Ongoing => {
+            let active_board = game.active_board();
+            if active_board.is_empty() {
+                println!("{} to move, anywhere", game.current_player());
+            } else {
+                println!(
+                    "{} to move, in board {active_board} ({active_board:#})",
+                    game.current_player()
+                );
+            }
+        }
+        Outcome::Won(winner) => println!("{winner} won the game!"),
+        Outcome::Stalemate => println!("A stalemate has occured, nobody wins"),
+    }
+}
+
+fn side_index(state: State) -> usize {
+    match state {
+        State::Circle => 0,
+        State::Cross => 1,
+    }
+}
+
+fn main() {
+    let (num_layers, rules, players) = match parse_args() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+    };
+    let mut bots = players.map(|player| match player.make_bot() {
+        Ok(bot) => bot,
+        Err(error) => {
+            eprintln!("failed to start the engine: {error}");
+            std::process::exit(1);
+        }
+    });
+
+    let mut game = Game::new(num_layers, rules);
+    println!("Type 'help' for a list of commands");
+    print_game(&game);
+
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        if game.status() == Outcome::Ongoing {
+            if let Some(bot) = &mut bots[side_index(game.current_player())] {
+                let Some(mv) = bot.choose_move(&game) else {
+                    eprintln!("{} could not find a move", bot.name());
+                    return;
+                };
+                let player = game.current_player();
+                println!("{player} plays {}", mv.path);
+                // a bot can be an external engine, which isn't trusted to only send legal moves
+                if let Err(error) = game.play(mv) {
+                    eprintln!("{} played an illegal move: {error}", bot.name());
+                    println!("{player} forfeits, {} won the game!", player.opponent());
+                    return;
+                }
+                print_game(&game);
+                continue;
+            }
+        }
+
+        print!("> ");
+        _ = std::io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            return;
+        };
+        match line.trim() {
+            "" => {}
+            "quit" | "exit" => return,
+            "help" => println!("{HELP}"),
+            "undo" => {
+                if game.undo().is_some() {
+                    // take back the computer's moves as well, otherwise it would just play them again
+                    while bots[side_index(game.current_player())].is_some() && game.undo().is_some()
+                    {
+                    }
+                    print_game(&game);
+                }
+            }
+            "redo" => {
+                if game.redo().is_some() {
+                    print_game(&game);
+                }
+            }
+            "moves" => {
+                let moves = game.moves().map(|mv| mv.path.to_string());
+                println!("{}", moves.collect::<Vec<_>>().join(" "));
+            }
+            "legal" => {
+                let moves = game.legal_moves().into_iter().map(|mv| mv.path.to_string());
+                println!("{}", moves.collect::<Vec<_>>().join(" "));
+            }
+            "position" => println!("{}", game.to_notation()),
+            input => match input.parse::<CellPath>() {
+                Ok(path) => match game.play(Move { path }) {
+                    Ok(_) => print_game(&game),
+                    Err(error) => println!("Illegal move: {error}"),
+                },
+                Err(error) => println!("{error}, type 'help' for a list of commands"),
+            },
+        }
+    }
+}
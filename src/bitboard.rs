@@ -0,0 +1,627 @@
+use rand::Rng;
+
+use crate::{
+    Board, BoardShape, CellPath, DrawPolicy, Element, Game, Misere, Outcome, Placement, Rules,
+    State,
+};
+
+/// The bit of every cell of a three by three board, cell `(x, y)` is bit `x * 3 + y` like the cells of a board
+const FULL: u16 = 0x1ff;
+
+/// The cells of every row, column and diagonal as bit masks
+const LINE_MASKS: [u16; 8] = [
+    0b000_000_111,
+    0b000_111_000,
+    0b111_000_000,
+    0b001_001_001,
+    0b010_010_010,
+    0b100_100_100,
+    0b100_010_001,
+    0b001_010_100,
+];
+
+/// Whether a set of cells contains a line, for every possible set
+const HAS_LINE: [bool; 512] = {
+    let mut table = [false; 512];
+    let mut cells = 0;
+    while cells < 512 {
+        let mut line = 0;
+        while line < LINE_MASKS.len() {
+            if cells as u16 & LINE_MASKS[line] == LINE_MASKS[line] {
+                table[cells] = true;
+            }
+            line += 1;
+        }
+        cells += 1;
+    }
+    table
+};
+
+/// A single three by three board of a [`BitBoard`]
+///
+/// On the deepest layer the masks are the cells each player has played, on the layers above they are the
+/// cached results of the sub-boards
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PackedBoard {
+    pub circle: u16,
+    pub cross: u16,
+    /// The sub-boards that nobody can win anymore
+    pub drawn: u16,
+    /// The sub-boards that have been won and collapsed
+    pub collapsed: u16,
+}
+
+impl PackedBoard {
+    pub fn winner(self) -> Option<State> {
+        if HAS_LINE[self.circle as usize] {
+            Some(State::Circle)
+        } else if HAS_LINE[self.cross as usize] {
+            Some(State::Cross)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_stalemate(self) -> bool {
+        self.circle | self.cross | self.drawn == FULL && self.winner().is_none()
+    }
+
+    /// The cells that are neither owned nor closed
+    pub fn open_cells(self) -> u16 {
+        FULL & !(self.circle | self.cross | self.drawn)
+    }
+
+    /// Stores the result of a sub-board in the cell at `bit`
+    fn set_result(&mut self, bit: u16, board: PackedBoard) {
+        self.circle &= !bit;
+        self.cross &= !bit;
+        self.drawn &= !bit;
+        self.collapsed &= !bit;
+        match board.winner() {
+            Some(State::Circle) => self.circle |= bit,
+            Some(State::Cross) => self.cross |= bit,
+            None if board.is_stalemate() => self.drawn |= bit,
+            None => return,
+        }
+        if board.winner().is_some() {
+            self.collapsed |= bit;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitBoardError {
+    /// A board isn't three by three with three in a row
+    NotThreeByThree(BoardShape),
+    /// The sub-boards don't all go down to the same depth, or a cell stands in for a whole sub-board
+    Irregular,
+    /// The board has more layers than [`Rules::MAX_LAYERS`]
+    TooManyLayers,
+    /// Drawn sub-boards count for someone or misère is played, which a bit board doesn't follow
+    UnsupportedRules,
+}
+
+impl std::fmt::Display for BitBoardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitBoardError::NotThreeByThree(shape) => {
+                write!(f, "a bit board can't hold a board of shape {shape}")
+            }
+            BitBoardError::Irregular => {
+                write!(
+                    f,
+                    "the sub-boards of a bit board all have to be equally deep"
+                )
+            }
+            BitBoardError::TooManyLayers => {
+                write!(
+                    f,
+                    "a bit board can't have more than {} layers",
+                    Rules::MAX_LAYERS
+                )
+            }
+            BitBoardError::UnsupportedRules => {
+                write!(f, "a bit board only follows dead draws without misère")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BitBoardError {}
+
+/// A compact copy of a board made of three by three boards, for searching and simulating quickly
+///
+/// Every board of every layer is packed into a [`PackedBoard`], stored one layer after the other starting
+/// with the outermost board, so the sub-boards of the `i`th board of a layer are boards `9 * i` to `9 * i + 8`
+/// of the next layer. Results of sub-boards are updated when a cell changes, following the default rules:
+/// drawn sub-boards count for nobody and a won sub-board goes to the player who completed the line.
+///
+/// Cells are numbered in the order of [`Game::legal_moves`], so the position `x * 3 + y` of a cell on each layer
+/// is a digit of its number in base 9, starting with the outermost layer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitBoard {
+    num_layers: usize,
+    boards: Vec<PackedBoard>,
+}
+
+/// The index of the first board of `layer` in [`BitBoard::boards`]
+fn layer_start(layer: usize) -> usize {
+    (9usize.pow(layer as u32) - 1) / 8
+}
+
+fn position((x, y): (usize, usize)) -> usize {
+    assert!(x < 3 && y < 3, "a bit board only has three by three boards");
+    x * 3 + y
+}
+
+fn cell_bit(cell: (usize, usize)) -> u16 {
+    1 << position(cell)
+}
+
+impl BitBoard {
+    pub fn new(num_layers: usize) -> Self {
+        assert!((1..=Rules::MAX_LAYERS).contains(&num_layers));
+        Self {
+            num_layers,
+            boards: vec![PackedBoard::default(); layer_start(num_layers)],
+        }
+    }
+
+    /// Packs the board of `game`, as long as its rules are the ones a bit board follows
+    pub fn from_game(game: &Game) -> Result<Self, BitBoardError> {
+        let rules = game.rules();
+        if rules.draw_policy != DrawPolicy::default() || rules.misere != Misere::default() {
+            return Err(BitBoardError::UnsupportedRules);
+        }
+        Self::try_from(game.board())
+    }
+
+    pub fn num_layers(&self) -> usize {
+        self.num_layers
+    }
+
+    /// The packed outermost board
+    pub fn root(&self) -> PackedBoard {
+        self.boards[0]
+    }
+
+    pub fn get_winner(&self) -> Option<State> {
+        self.root().winner()
+    }
+
+    pub fn is_stalemate(&self) -> bool {
+        self.root().is_stalemate()
+    }
+
+    /// The number of the cell at `path`, which has to go down to the deepest layer
+    pub fn cell_index(&self, path: &[(usize, usize)]) -> usize {
+        assert_eq!(
+            path.len(),
+            self.num_layers,
+            "the path has to point to a cell"
+        );
+        path.iter().fold(0, |cell, &xy| cell * 9 + position(xy))
+    }
+
+    /// The path of the cell numbered `cell`
+    pub fn cell_path(&self, cell: usize) -> CellPath {
+        CellPath(
+            (0..self.num_layers)
+                .rev()
+                .map(|below| {
+                    let position = cell / 9usize.pow(below as u32) % 9;
+                    (position / 3, position % 3)
+                })
+                .collect(),
+        )
+    }
+
+    /// The index in [`BitBoard::boards`] of the board on `layer` containing `cell`, with the bit of the cell or
+    /// sub-board in it
+    fn link(&self, cell: usize, layer: usize) -> (usize, u16) {
+        let within_layer = cell / 9usize.pow((self.num_layers - 1 - layer) as u32);
+        (
+            layer_start(layer) + within_layer / 9,
+            1 << (within_layer % 9),
+        )
+    }
+
+    /// The state of the cell numbered `cell`
+    pub fn cell(&self, cell: usize) -> Option<State> {
+        let (index, bit) = self.link(cell, self.num_layers - 1);
+        let board = self.boards[index];
+        if board.circle & bit != 0 {
+            Some(State::Circle)
+        } else if board.cross & bit != 0 {
+            Some(State::Cross)
+        } else {
+            None
+        }
+    }
+
+    /// Sets the cell numbered `cell` and updates the results of the boards containing it
+    pub fn set_cell(&mut self, cell: usize, state: Option<State>) {
+        let (index, bit) = self.link(cell, self.num_layers - 1);
+        let leaf = &mut self.boards[index];
+        leaf.circle &= !bit;
+        leaf.cross &= !bit;
+        match state {
+            Some(State::Circle) => leaf.circle |= bit,
+            Some(State::Cross) => leaf.cross |= bit,
+            None => {}
+        }
+
+        for layer in (0..self.num_layers - 1).rev() {
+            let (parent, bit) = self.link(cell, layer);
+            let (child, _) = self.link(cell, layer + 1);
+            let before = self.boards[parent];
+            let child = self.boards[child];
+            self.boards[parent].set_result(bit, child);
+            // nothing changes further up once a board keeps its result
+            if self.boards[parent] == before {
+                break;
+            }
+        }
+    }
+
+    /// The layer of the board at `board_path` and its index within the layer
+    fn board_at(&self, board_path: &[(usize, usize)]) -> (usize, usize) {
+        assert!(
+            board_path.len() < self.num_layers,
+            "the path has to point to a board"
+        );
+        let within_layer = board_path
+            .iter()
+            .fold(0, |board, &xy| board * 9 + position(xy));
+        (board_path.len(), within_layer)
+    }
+
+    /// Whether the board at `within_layer` of `layer` or a board containing it is won or drawn
+    fn is_closed(&self, layer: usize, within_layer: usize) -> bool {
+        (1..=layer).any(|layer_below| {
+            let board = within_layer / 9usize.pow((layer - layer_below) as u32);
+            let parent = self.boards[layer_start(layer_below - 1) + board / 9];
+            parent.open_cells() & 1 << (board % 9) == 0
+        })
+    }
+
+    /// The numbers of the empty cells inside of the board at `board_path` that can still be played,
+    /// an empty path means the whole board
+    pub fn empty_cells(&self, board_path: &[(usize, usize)]) -> impl Iterator<Item = usize> + '_ {
+        let (layer, within_layer) = self.board_at(board_path);
+        let num_cells = 9usize.pow((self.num_layers - layer) as u32);
+        let playable = self.get_winner().is_none()
+            && !self.is_stalemate()
+            && !self.is_closed(layer, within_layer);
+        let cells = if playable {
+            within_layer * num_cells..(within_layer + 1) * num_cells
+        } else {
+            0..0
+        };
+        cells.filter(move |&cell| {
+            (layer..self.num_layers).all(|layer| {
+                let (index, bit) = self.link(cell, layer);
+                self.boards[index].open_cells() & bit != 0
+            })
+        })
+    }
+
+    /// The number of cells that can still be played inside of the board at `within_layer` of `layer`
+    fn count_open_cells(&self, layer: usize, within_layer: usize) -> usize {
+        let open = self.boards[layer_start(layer) + within_layer].open_cells();
+        if layer + 1 == self.num_layers {
+            return open.count_ones() as usize;
+        }
+        (0..9)
+            .filter(|position| open & 1 << position != 0)
+            .map(|position| self.count_open_cells(layer + 1, within_layer * 9 + position))
+            .sum()
+    }
+
+    /// The number of the cell at `index` among the ones counted by [`BitBoard::count_open_cells`]
+    fn nth_open_cell(&self, layer: usize, within_layer: usize, mut index: usize) -> Option<usize> {
+        let open = self.boards[layer_start(layer) + within_layer].open_cells();
+        for position in (0..9).filter(|position| open & 1 << position != 0) {
+            let child = within_layer * 9 + position;
+            let count = if layer + 1 == self.num_layers {
+                1
+            } else {
+                self.count_open_cells(layer + 1, child)
+            };
+            if index < count {
+                return if layer + 1 == self.num_layers {
+                    Some(child)
+                } else {
+                    self.nth_open_cell(layer + 1, child, index)
+                };
+            }
+            index -= count;
+        }
+        None
+    }
+
+    /// The board the move after `cell` has to be played in, the same as [`Rules::next_active_board`]
+    fn next_active_board(&self, placement: Placement, cell: usize) -> (usize, usize) {
+        if placement == Placement::Free || self.num_layers < 2 {
+            return (0, 0);
+        }
+        let (mut layer, mut within_layer) = (
+            self.num_layers - 1,
+            cell % 9usize.pow(self.num_layers as u32 - 1),
+        );
+        while layer > 0 && self.is_closed(layer, within_layer) {
+            layer -= 1;
+            within_layer /= 9;
+        }
+        (layer, within_layer)
+    }
+
+    /// Plays random moves until the game is over and takes them back again, returning how the game ended.
+    /// The first move is made by `player` inside of the board at `active_board`, and `placement` decides where
+    /// the moves after it have to go
+    pub fn random_playout(
+        &mut self,
+        placement: Placement,
+        active_board: &[(usize, usize)],
+        mut player: State,
+        rng: &mut impl Rng,
+    ) -> Outcome {
+        let (mut layer, mut within_layer) = self.board_at(active_board);
+        let mut played = vec![];
+        let outcome = loop {
+            if let Some(winner) = self.get_winner() {
+                break Outcome::Won(winner);
+            }
+            if self.is_stalemate() {
+                break Outcome::Stalemate;
+            }
+            let count = self.count_open_cells(layer, within_layer);
+            let cell = self
+                .nth_open_cell(layer, within_layer, rng.gen_range(0..count))
+                .expect("a board that isn't closed should have an empty cell");
+            self.set_cell(cell, Some(player));
+            played.push(cell);
+            player = player.opponent();
+            (layer, within_layer) = self.next_active_board(placement, cell);
+        };
+        for cell in played.into_iter().rev() {
+            self.set_cell(cell, None);
+        }
+        outcome
+    }
+
+    /// Packs `board` into the board at `within_layer` of `layer`
+    fn fill(
+        &mut self,
+        board: &Board,
+        layer: usize,
+        within_layer: usize,
+    ) -> Result<(), BitBoardError> {
+        if board.shape() != BoardShape::default() {
+            return Err(BitBoardError::NotThreeByThree(board.shape()));
+        }
+        let index = layer_start(layer) + within_layer;
+        let deepest = layer + 1 == self.num_layers;
+        for (x, column) in board.elements.iter().enumerate() {
+            for (y, element) in column.iter().enumerate() {
+                let bit = cell_bit((x, y));
+                let child = within_layer * 9 + position((x, y));
+                match (element, deepest) {
+                    (Element::State(None), true) => {}
+                    (&Element::State(Some(state)), true) => match state {
+                        State::Circle => self.boards[index].circle |= bit,
+                        State::Cross => self.boards[index].cross |= bit,
+                    },
+                    (Element::Board(inner), false) => {
+                        self.fill(inner, layer + 1, child)?;
+                        let inner = self.boards[layer_start(layer + 1) + child];
+                        self.boards[index].set_result(bit, inner);
+                        // a won board that wasn't collapsed stays a board
+                        self.boards[index].collapsed &= !bit;
+                    }
+                    (&Element::Won { winner, ref board }, false) => {
+                        self.fill(board, layer + 1, child)?;
+                        let packed = &mut self.boards[index];
+                        match winner {
+                            State::Circle => packed.circle |= bit,
+                            State::Cross => packed.cross |= bit,
+                        }
+                        packed.collapsed |= bit;
+                    }
+                    _ => return Err(BitBoardError::Irregular),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Unpacks the board at `within_layer` of `layer`
+    fn unpack(&self, layer: usize, within_layer: usize) -> Board {
+        let packed = self.boards[layer_start(layer) + within_layer];
+        let deepest = layer + 1 == self.num_layers;
+        let mut board = Board::default();
+        for (x, column) in board.elements.iter_mut().enumerate() {
+            for (y, element) in column.iter_mut().enumerate() {
+                let bit = cell_bit((x, y));
+                let owner = if packed.circle & bit != 0 {
+                    Some(State::Circle)
+                } else if packed.cross & bit != 0 {
+                    Some(State::Cross)
+                } else {
+                    None
+                };
+                if deepest {
+                    *element = Element::State(owner);
+                    continue;
+                }
+                let inner = Box::new(self.unpack(layer + 1, within_layer * 9 + position((x, y))));
+                *element = match owner {
+                    Some(winner) if packed.collapsed & bit != 0 => Element::Won {
+                        winner,
+                        board: inner,
+                    },
+                    _ => Element::Board(inner),
+                };
+            }
+        }
+        board
+    }
+}
+
+impl TryFrom<&Board> for BitBoard {
+    type Error = BitBoardError;
+
+    fn try_from(board: &Board) -> Result<Self, Self::Error> {
+        if board.depth() > Rules::MAX_LAYERS {
+            return Err(BitBoardError::TooManyLayers);
+        }
+        let mut bitboard = BitBoard::new(board.depth());
+        bitboard.fill(board, 0, 0)?;
+        Ok(bitboard)
+    }
+}
+
+impl From<&BitBoard> for Board {
+    fn from(bitboard: &BitBoard) -> Self {
+        bitboard.unpack(0, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{seq::SliceRandom, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for notation in [
+            "9",
+            "OXO2X3",
+            "[9][9][9][9][X8][9][9][9][9]",
+            "O[OOO6][XX7][9][9][9][9][9][9][9]",
+            // a won board that hasn't been collapsed stays a board
+            "[OOO6][9][9][9][9][9][9][9][9]",
+        ] {
+            let board = notation.parse::<Board>().unwrap();
+            let bitboard = BitBoard::try_from(&board).unwrap();
+            assert_eq!(Board::from(&bitboard).to_string(), notation);
+        }
+        assert_eq!(
+            BitBoard::try_from(&"O[9]8".parse::<Board>().unwrap()),
+            Err(BitBoardError::Irregular)
+        );
+        let shape = BoardShape {
+            size: 4,
+            win_length: 3,
+        };
+        assert_eq!(
+            BitBoard::try_from(&Board::empty(shape)),
+            Err(BitBoardError::NotThreeByThree(shape))
+        );
+    }
+
+    #[test]
+    fn matches_the_board_during_random_games() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for (num_layers, placement) in [
+            (1, Placement::Free),
+            (2, Placement::Free),
+            (2, Placement::SentTo),
+            (2, Placement::SentTo),
+            (3, Placement::Free),
+            (3, Placement::SentTo),
+        ] {
+            let rules = Rules {
+                placement,
+                ..Default::default()
+            };
+            let mut game = Game::new(num_layers, rules);
+            let mut bitboard = BitBoard::from_game(&game).unwrap();
+            loop {
+                let expected = game
+                    .legal_moves()
+                    .into_iter()
+                    .map(|mv| mv.path)
+                    .collect::<Vec<_>>();
+                let cells = bitboard
+                    .empty_cells(game.active_board())
+                    .collect::<Vec<_>>();
+                let paths = cells.iter().map(|&cell| bitboard.cell_path(cell));
+                assert_eq!(paths.collect::<Vec<_>>(), expected);
+                assert_eq!(bitboard.get_winner(), game.board().get_winner());
+                assert_eq!(bitboard.is_stalemate(), game.board().is_stalemate());
+                assert_eq!(Board::from(&bitboard).to_string(), game.board().to_string());
+
+                let Some(&cell) = cells.choose(&mut rng) else {
+                    break;
+                };
+                let path = bitboard.cell_path(cell);
+                assert_eq!(bitboard.cell_index(&path), cell);
+                bitboard.set_cell(cell, Some(game.current_player()));
+                assert_eq!(bitboard.cell(cell), Some(game.current_player()));
+                game.play(crate::Move { path }).unwrap();
+                assert_eq!(
+                    bitboard.next_active_board(placement, cell),
+                    bitboard.board_at(game.active_board())
+                );
+            }
+
+            // taking back every move leaves an empty board
+            for mv in game.moves().cloned().collect::<Vec<_>>() {
+                bitboard.set_cell(bitboard.cell_index(&mv.path), None);
+            }
+            assert_eq!(bitboard, BitBoard::new(num_layers));
+        }
+    }
+
+    #[test]
+    fn playouts_leave_the_board_as_it_was() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let rules = Rules {
+            placement: Placement::SentTo,
+            ..Default::default()
+        };
+        let mut game = Game::new(3, rules);
+        game.play(crate::Move {
+            path: "4.4.0".parse().unwrap(),
+        })
+        .unwrap();
+        let mut bitboard = BitBoard::from_game(&game).unwrap();
+        let before = bitboard.clone();
+        for _ in 0..20 {
+            let outcome = bitboard.random_playout(
+                Placement::SentTo,
+                game.active_board(),
+                game.current_player(),
+                &mut rng,
+            );
+            assert_ne!(outcome, Outcome::Ongoing);
+            assert_eq!(bitboard, before);
+        }
+
+        // the only empty cell completes a line, and a finished game stays finished
+        for (notation, outcome) in [
+            ("OO1XXOXOX O", Outcome::Won(State::Circle)),
+            ("OOOXX4 X", Outcome::Won(State::Circle)),
+            ("OXOOXXX1O O", Outcome::Stalemate),
+        ] {
+            let game = Game::from_notation(notation, Rules::default()).unwrap();
+            let mut bitboard = BitBoard::from_game(&game).unwrap();
+            let player = game.current_player();
+            assert_eq!(
+                bitboard.random_playout(Placement::Free, &[], player, &mut rng),
+                outcome
+            );
+        }
+
+        let rules = Rules {
+            misere: Misere::TopLevel,
+            ..Default::default()
+        };
+        assert_eq!(
+            BitBoard::from_game(&Game::new(2, rules)),
+            Err(BitBoardError::UnsupportedRules)
+        );
+    }
+}
@@ -1,9 +1,36 @@
+use crate::{DrawPolicy, Misere};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum State {
     Circle,
     Cross,
 }
 
+impl State {
+    pub fn opponent(self) -> State {
+        match self {
+            State::Circle => State::Cross,
+            State::Cross => State::Circle,
+        }
+    }
+
+    /// The name used for this side in text protocols
+    pub fn name(self) -> &'static str {
+        match self {
+            State::Circle => "circle",
+            State::Cross => "cross",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<State> {
+        [State::Circle, State::Cross]
+            .into_iter()
+            .find(|state| state.name() == name)
+    }
+}
+
 impl std::fmt::Display for State {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -13,10 +40,19 @@ impl std::fmt::Display for State {
     }
 }
 
+/// With the `serde` feature this is serialized as `{"state": null}`, `{"state": "circle"}`, `{"board": {...}}`
+/// or `{"won": {"winner": "circle", "board": {...}}}`
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Element {
     State(Option<State>),
     Board(Box<Board>),
+    /// A sub-board that has been won, its contents are kept so they can still be shown and taken back
+    Won {
+        winner: State,
+        board: Box<Board>,
+    },
 }
 
 impl Element {
@@ -24,6 +60,30 @@ impl Element {
         match self {
             &Element::State(state) => state,
             Element::Board(board) => board.get_winner(),
+            &Element::Won { winner, .. } => Some(winner),
+        }
+    }
+
+    /// Whether nothing more can be played inside this element
+    pub fn is_closed(&self) -> bool {
+        self.is_closed_with(DrawPolicy::Dead)
+    }
+
+    pub fn is_closed_with(&self, policy: DrawPolicy) -> bool {
+        self.resolve(policy) != Resolution::Open
+    }
+
+    fn resolve(&self, policy: DrawPolicy) -> Resolution {
+        self.resolve_in(policy, &mut vec![])
+    }
+
+    fn resolve_in(&self, policy: DrawPolicy, buffer: &mut Vec<Resolution>) -> Resolution {
+        match self {
+            &Element::State(None) => Resolution::Open,
+            &Element::State(Some(state)) | &Element::Won { winner: state, .. } => {
+                Resolution::Won(state)
+            }
+            Element::Board(board) => board.resolve_in(policy, buffer),
         }
     }
 }
@@ -34,84 +94,486 @@ impl Default for Element {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+/// Whether a board or cell has been decided
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Resolution {
+    Open,
+    Won(State),
+    /// Nobody can win anymore, `majority` is the player who owns more of the cells
+    Drawn {
+        majority: Option<State>,
+    },
+}
+
+/// The size of a board and how many cells in a row it takes to win it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardShape {
+    pub size: usize,
+    pub win_length: usize,
+}
+
+impl Default for BoardShape {
+    fn default() -> Self {
+        Self {
+            size: 3,
+            win_length: 3,
+        }
+    }
+}
+
+impl BoardShape {
+    /// The largest size a board can have, so every column can be written as a letter
+    pub const MAX_SIZE: usize = 26;
+
+    pub fn is_valid(self) -> bool {
+        (1..=Self::MAX_SIZE).contains(&self.size) && (1..=self.size).contains(&self.win_length)
+    }
+
+    /// The cells of every run of `win_length` cells along a row, column or diagonal
+    pub fn lines(self) -> impl Iterator<Item = impl Iterator<Item = (usize, usize)> + Clone> {
+        let BoardShape { size, win_length } = self;
+        let (size, steps) = (size as isize, win_length as isize - 1);
+        LINE_DIRECTIONS.into_iter().flat_map(move |(dx, dy)| {
+            (0..size)
+                .flat_map(move |x| (0..size).map(move |y| (x, y)))
+                .filter(move |&(x, y)| {
+                    (0..size).contains(&(x + dx * steps)) && (0..size).contains(&(y + dy * steps))
+                })
+                .map(move |(x, y)| {
+                    (0..=steps).map(move |i| ((x + dx * i) as usize, (y + dy * i) as usize))
+                })
+        })
+    }
+}
+
+/// The steps from one cell of a line to the next, for columns, rows and both diagonals
+const LINE_DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (-1, 1)];
+
+impl std::fmt::Display for BoardShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.size, self.win_length)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBoardShapeError(pub String);
+
+impl std::fmt::Display for ParseBoardShapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "'{}' is not a board shape, expected <size>:<in a row> with 1 <= in a row <= size <= {}",
+            self.0,
+            BoardShape::MAX_SIZE
+        )
+    }
+}
+
+impl std::error::Error for ParseBoardShapeError {}
+
+/// Parses `<size>:<win length>`, or just `<size>` for a win length of the whole size
+impl std::str::FromStr for BoardShape {
+    type Err = ParseBoardShapeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (size, win_length) = s.split_once(':').unwrap_or((s, s));
+        let shape = BoardShape {
+            size: size.parse().map_err(|_| ParseBoardShapeError(s.into()))?,
+            win_length: win_length
+                .parse()
+                .map_err(|_| ParseBoardShapeError(s.into()))?,
+        };
+        if shape.is_valid() {
+            Ok(shape)
+        } else {
+            Err(ParseBoardShapeError(s.into()))
+        }
+    }
+}
+
+/// With the `serde` feature this is serialized as `{"elements": [[...], ...], "win_length": 3}`, indexed by x then y.
+/// `win_length` can be left out for classic boards, and boards that aren't square or have an invalid shape are
+/// rejected when deserializing
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UncheckedBoard"))]
 pub struct Board {
-    pub elements: [[Element; 3]; 3],
+    /// The columns of the board, every column has as many elements as there are columns
+    pub elements: Vec<Vec<Element>>,
+    pub win_length: usize,
+}
+
+/// A deserialized board before its shape has been checked
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct UncheckedBoard {
+    elements: Vec<Vec<Element>>,
+    #[serde(default = "default_win_length")]
+    win_length: usize,
+}
+
+#[cfg(feature = "serde")]
+fn default_win_length() -> usize {
+    BoardShape::default().win_length
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<UncheckedBoard> for Board {
+    type Error = String;
+
+    fn try_from(board: UncheckedBoard) -> Result<Self, Self::Error> {
+        let UncheckedBoard {
+            elements,
+            win_length,
+        } = board;
+        let shape = BoardShape {
+            size: elements.len(),
+            win_length,
+        };
+        if !shape.is_valid() {
+            return Err(format!("a board can't have the shape {shape}"));
+        }
+        if elements.iter().any(|column| column.len() != shape.size) {
+            return Err("a board has to be square".into());
+        }
+        Ok(Board {
+            elements,
+            win_length,
+        })
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::empty(BoardShape::default())
+    }
 }
 
 impl Board {
-    pub fn is_stalemate(&self) -> bool {
-        self.elements.iter().flatten().all(|state| match state {
-            Element::State(state) => state.is_some(),
-            Element::Board(board) => board.is_stalemate() || board.get_winner().is_some(),
-        }) && self.get_winner().is_none()
+    /// A classic board, `num_layers` deep with three by three boards on every layer
+    pub fn new(num_layers: usize) -> Self {
+        Self::with_shapes(&vec![BoardShape::default(); num_layers])
     }
 
-    pub fn get_winner(&self) -> Option<State> {
-        let states: [[Option<State>; 3]; 3] =
-            std::array::from_fn(|x| std::array::from_fn(|y| self.elements[x][y].get_state()));
-
-        fn check_winner(state: State, states: &[[Option<State>; 3]; 3]) -> bool {
-            // Check vertical
-            for column in states {
-                if column.iter().all(|&s| s == Some(state)) {
-                    return true;
-                }
-            }
+    /// A board with one layer for every shape, starting from the outermost one
+    pub fn with_shapes(shapes: &[BoardShape]) -> Self {
+        let (&shape, inner) = shapes
+            .split_first()
+            .expect("a board needs at least one layer");
+        let mut board = Self::empty(shape);
+        if !inner.is_empty() {
+            board.elements.iter_mut().flatten().for_each(|e| {
+                *e = Element::Board(Box::new(Self::with_shapes(inner)));
+            });
+        }
+        board
+    }
 
-            // Check horizontal
-            for y in 0..3 {
-                let mut won = true;
-                #[allow(clippy::needless_range_loop)]
-                for x in 0..3 {
-                    if states[x][y] != Some(state) {
-                        won = false;
-                        break;
+    /// A single layer board without any moves
+    pub fn empty(shape: BoardShape) -> Self {
+        Self {
+            elements: (0..shape.size)
+                .map(|_| (0..shape.size).map(|_| Element::default()).collect())
+                .collect(),
+            win_length: shape.win_length,
+        }
+    }
+
+    /// The number of columns, which is also the number of rows
+    pub fn size(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn shape(&self) -> BoardShape {
+        BoardShape {
+            size: self.size(),
+            win_length: self.win_length,
+        }
+    }
+
+    /// The size of the boards on every layer, starting from this one and following the deepest sub-boards
+    pub fn layer_sizes(&self) -> Vec<usize> {
+        let inner = self
+            .elements
+            .iter()
+            .flatten()
+            .filter_map(|element| match element {
+                Element::State(_) => None,
+                Element::Board(board) | Element::Won { board, .. } => Some(board.layer_sizes()),
+            })
+            .max_by_key(Vec::len)
+            .unwrap_or_default();
+        std::iter::once(self.size()).chain(inner).collect()
+    }
+
+    /// The number of nested layers of boards, a board without sub-boards has a depth of 1
+    pub fn depth(&self) -> usize {
+        1 + self
+            .elements
+            .iter()
+            .flatten()
+            .map(|element| match element {
+                Element::State(_) => 0,
+                Element::Board(board) | Element::Won { board, .. } => board.depth(),
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the states of all the smallest cells as rows, starting from the top row
+    ///
+    /// A state that isn't on the deepest layer or a won sub-board fills all of the cells it covers
+    pub fn leaf_grid(&self) -> Vec<Vec<Option<State>>> {
+        fn fill(
+            board: &Board,
+            grid: &mut [Vec<Option<State>>],
+            (left, bottom): (usize, usize),
+            cell_size: usize,
+        ) {
+            for (x, column) in board.elements.iter().enumerate() {
+                for (y, element) in column.iter().enumerate() {
+                    let origin = (left + x * cell_size, bottom + y * cell_size);
+                    match element {
+                        Element::Board(board) => {
+                            fill(board, grid, origin, cell_size / board.size())
+                        }
+                        element => {
+                            for y in origin.1..origin.1 + cell_size {
+                                let row = grid.len() - 1 - y;
+                                grid[row][origin.0..origin.0 + cell_size].fill(element.get_state());
+                            }
+                        }
                     }
                 }
-                if won {
-                    return true;
-                }
             }
+        }
 
-            // Check diagonal right
-            {
-                let mut won = true;
-                #[allow(clippy::needless_range_loop)]
-                for i in 0..3 {
-                    if states[i][i] != Some(state) {
-                        won = false;
-                        break;
-                    }
-                }
-                if won {
-                    return true;
-                }
+        let size = self.layer_sizes().into_iter().product();
+        let mut grid = vec![vec![None; size]; size];
+        fill(self, &mut grid, (0, 0), size / self.size());
+        grid
+    }
+
+    pub fn count_empty_cells(&self) -> usize {
+        self.elements
+            .iter()
+            .flatten()
+            .map(|element| match element {
+                Element::State(None) => 1,
+                Element::State(Some(_)) | Element::Won { .. } => 0,
+                Element::Board(board) => board.count_empty_cells(),
+            })
+            .sum()
+    }
+
+    /// Marks every sub-board that has a winner as won, so nothing more can be played in it
+    pub fn collapse_states(&mut self) {
+        self.collapse_states_with(DrawPolicy::Dead, Misere::Normal);
+    }
+
+    /// Like [`Board::collapse_states`], with drawn sub-boards counting as set by `policy`,
+    /// and a sub-board going to the player `misere` gives it to when a line is completed in it
+    ///
+    /// Sub-boards are only looked at through their winner once they are collapsed,
+    /// so the misère rule has to be applied here
+    pub fn collapse_states_with(&mut self, policy: DrawPolicy, misere: Misere) {
+        for element in self.elements.iter_mut().flatten() {
+            let Element::Board(board) = element else {
+                continue;
+            };
+            board.collapse_states_with(policy, misere);
+            if let Some(line_owner) = board.get_winner_with(policy) {
+                let board = std::mem::take(board);
+                *element = Element::Won {
+                    winner: misere.sub_board_winner(line_owner),
+                    board,
+                };
             }
+        }
+    }
+
+    pub fn get_element(&self, path: &[(usize, usize)]) -> Option<&Element> {
+        let (&(x, y), rest) = path.split_first()?;
+        let element = self.elements.get(x)?.get(y)?;
+        if rest.is_empty() {
+            return Some(element);
+        }
+        match element {
+            Element::Board(board) => board.get_element(rest),
+            Element::State(_) | Element::Won { .. } => None,
+        }
+    }
+
+    pub fn get_element_mut(&mut self, path: &[(usize, usize)]) -> Option<&mut Element> {
+        let (&(x, y), rest) = path.split_first()?;
+        let element = self.elements.get_mut(x)?.get_mut(y)?;
+        if rest.is_empty() {
+            return Some(element);
+        }
+        match element {
+            Element::Board(board) => board.get_element_mut(rest),
+            Element::State(_) | Element::Won { .. } => None,
+        }
+    }
+
+    pub fn is_stalemate(&self) -> bool {
+        self.is_stalemate_with(DrawPolicy::Dead)
+    }
+
+    pub fn get_winner(&self) -> Option<State> {
+        self.get_winner_with(DrawPolicy::Dead)
+    }
+
+    /// Whether the board can't be won anymore, with drawn sub-boards counting as set by `policy`
+    pub fn is_stalemate_with(&self, policy: DrawPolicy) -> bool {
+        matches!(self.resolve(policy), Resolution::Drawn { .. })
+    }
+
+    /// The player with `win_length` in a row, with drawn sub-boards counting as set by `policy`
+    pub fn get_winner_with(&self, policy: DrawPolicy) -> Option<State> {
+        match self.resolve(policy) {
+            Resolution::Won(winner) => Some(winner),
+            _ => None,
+        }
+    }
+
+    fn resolve(&self, policy: DrawPolicy) -> Resolution {
+        self.resolve_in(policy, &mut vec![])
+    }
+
+    /// The cells of this board are resolved into the end of `buffer` and taken out again, so a whole tree of boards
+    /// is resolved with a single buffer
+    fn resolve_in(&self, policy: DrawPolicy, buffer: &mut Vec<Resolution>) -> Resolution {
+        let start = buffer.len();
+        for element in self.elements.iter().flatten() {
+            let resolution = element.resolve_in(policy, buffer);
+            buffer.push(resolution);
+        }
+        let resolution = resolve_cells(self.shape(), &buffer[start..], policy);
+        buffer.truncate(start);
+        resolution
+    }
+
+    /// The row, column or diagonal that won this board, if any
+    pub fn winning_line(&self) -> Option<Vec<(usize, usize)>> {
+        self.winning_line_with(DrawPolicy::default())
+            .map(|(_, line)| line)
+    }
+
+    /// The player with `win_length` in a row and the cells of that line, with drawn sub-boards counting as set by
+    /// `policy` like in [`Board::get_winner_with`]
+    pub fn winning_line_with(&self, policy: DrawPolicy) -> Option<(State, Vec<(usize, usize)>)> {
+        let mut buffer = vec![];
+        let resolutions: Vec<Resolution> = self
+            .elements
+            .iter()
+            .flatten()
+            .map(|element| element.resolve_in(policy, &mut buffer))
+            .collect();
+        let shape = self.shape();
+        let Resolution::Won(line_owner) = resolve_cells(shape, &resolutions, policy) else {
+            return None;
+        };
+        let line = shape
+            .lines()
+            .map(Iterator::collect::<Vec<_>>)
+            .find(|line| {
+                completes_line(
+                    line_owner,
+                    line.iter().map(|&(x, y)| resolutions[x * shape.size + y]),
+                    policy,
+                )
+            })?;
+        Some((line_owner, line))
+    }
+}
+
+/// Which player a cell counts for in a line, and whether it counts at all
+fn claim(resolution: Resolution, policy: DrawPolicy) -> (Option<State>, bool) {
+    match resolution {
+        Resolution::Won(winner) => (Some(winner), true),
+        Resolution::Drawn { majority } => match policy {
+            DrawPolicy::Dead => (None, false),
+            DrawPolicy::CountsForBoth => (None, true),
+            DrawPolicy::MajorityWins => (majority, majority.is_some()),
+        },
+        Resolution::Open => (None, false),
+    }
+}
+
+/// Whether `cells` make a line for `state`, which needs at least one cell that was actually won by it
+fn completes_line(
+    state: State,
+    cells: impl IntoIterator<Item = Resolution>,
+    policy: DrawPolicy,
+) -> bool {
+    let mut owned = false;
+    let complete = cells.into_iter().all(|cell| {
+        let (owner, counts) = claim(cell, policy);
+        owned |= owner == Some(state);
+        counts && owner.is_none_or(|owner| owner == state)
+    });
+    complete && owned
+}
+
+/// How a board with `shape` is decided, given how each of its cells is decided, indexed by `x * size + y`
+pub(crate) fn resolve_cells(
+    shape: BoardShape,
+    resolutions: &[Resolution],
+    policy: DrawPolicy,
+) -> Resolution {
+    let size = shape.size;
 
-            // Check diagonal left
-            {
-                let mut won = true;
-                for i in 0..3 {
-                    if states[2 - i][i] != Some(state) {
-                        won = false;
-                        break;
+    // this runs for every board on every move, so it walks the lines without building them
+    let has_line = |state: State| {
+        let steps = shape.win_length as isize - 1;
+        for (dx, dy) in LINE_DIRECTIONS {
+            for x in 0..size as isize {
+                for y in 0..size as isize {
+                    let (end_x, end_y) = (x + dx * steps, y + dy * steps);
+                    if !(0..size as isize).contains(&end_x) || !(0..size as isize).contains(&end_y)
+                    {
+                        continue;
+                    }
+                    let cells = (0..=steps)
+                        .map(|i| resolutions[(x + dx * i) as usize * size + (y + dy * i) as usize]);
+                    if completes_line(state, cells, policy) {
+                        return true;
                     }
-                }
-                if won {
-                    return true;
                 }
             }
-
-            false
         }
+        false
+    };
 
-        if check_winner(State::Circle, &states) {
-            Some(State::Circle)
-        } else if check_winner(State::Cross, &states) {
-            Some(State::Cross)
-        } else {
-            None
+    let majority = || {
+        let count = |state: State| {
+            (0..size * size)
+                .filter(|&i| claim(resolutions[i], policy).0 == Some(state))
+                .count()
+        };
+        match count(State::Circle).cmp(&count(State::Cross)) {
+            std::cmp::Ordering::Greater => Some(State::Circle),
+            std::cmp::Ordering::Less => Some(State::Cross),
+            std::cmp::Ordering::Equal => None,
+        }
+    };
+    match (has_line(State::Circle), has_line(State::Cross)) {
+        (true, false) => Resolution::Won(State::Circle),
+        (false, true) => Resolution::Won(State::Cross),
+        // both players have a line when a drawn board that counts for both completes one for each of them at once,
+        // or in a position that was set up that way, and then nobody gets this board
+        (true, true) => Resolution::Drawn {
+            majority: majority(),
+        },
+        (false, false) => {
+            if resolutions.iter().all(|&r| r != Resolution::Open) {
+                Resolution::Drawn {
+                    majority: majority(),
+                }
+            } else {
+                Resolution::Open
+            }
         }
     }
 }
@@ -123,23 +585,24 @@ mod tests {
     #[test]
     fn nothing() {
         let board = Board {
-            elements: [
-                [
+            elements: vec![
+                vec![
                     Element::State(None),
                     Element::State(None),
                     Element::State(None),
                 ],
-                [
+                vec![
                     Element::State(None),
                     Element::State(None),
                     Element::State(None),
                 ],
-                [
+                vec![
                     Element::State(None),
                     Element::State(None),
                     Element::State(None),
                 ],
             ],
+            win_length: 3,
         };
         assert!(board.get_winner().is_none());
         assert!(!board.is_stalemate());
@@ -148,50 +611,195 @@ mod tests {
     #[test]
     fn cross_horizontal_win() {
         let board = Board {
-            elements: [
-                [
+            elements: vec![
+                vec![
                     Element::State(Some(State::Cross)),
                     Element::State(None),
                     Element::State(None),
                 ],
-                [
+                vec![
                     Element::State(Some(State::Cross)),
                     Element::State(None),
                     Element::State(None),
                 ],
-                [
+                vec![
                     Element::State(Some(State::Cross)),
                     Element::State(None),
                     Element::State(None),
                 ],
             ],
+            win_length: 3,
         };
         assert_eq!(board.get_winner(), Some(State::Cross));
+        assert_eq!(board.winning_line(), Some(vec![(0, 0), (1, 0), (2, 0)]));
         assert!(!board.is_stalemate());
     }
 
     #[test]
     fn circle_diagonal_win() {
         let board = Board {
-            elements: [
-                [
+            elements: vec![
+                vec![
                     Element::State(Some(State::Cross)),
                     Element::State(None),
                     Element::State(None),
                 ],
-                [
+                vec![
                     Element::State(None),
                     Element::State(Some(State::Cross)),
                     Element::State(None),
                 ],
-                [
+                vec![
                     Element::State(None),
                     Element::State(None),
                     Element::State(Some(State::Cross)),
                 ],
             ],
+            win_length: 3,
         };
         assert_eq!(board.get_winner(), Some(State::Cross));
+        assert_eq!(board.winning_line(), Some(vec![(0, 0), (1, 1), (2, 2)]));
         assert!(!board.is_stalemate());
     }
+
+    #[test]
+    fn lines_for_both_players_draw_the_board() {
+        let board: Board = "OOOXXX3".parse().unwrap();
+        assert_eq!(board.get_winner(), None);
+        assert!(board.is_stalemate());
+
+        // the drawn board completes a line for circle and one for cross
+        let board: Board = "[OXOOXXXOO]OOX2X2".parse().unwrap();
+        assert_eq!(board.get_winner_with(DrawPolicy::CountsForBoth), None);
+        assert!(board.is_stalemate_with(DrawPolicy::CountsForBoth));
+        assert_eq!(board.winning_line_with(DrawPolicy::CountsForBoth), None);
+        assert!(!board.is_stalemate_with(DrawPolicy::Dead));
+    }
+
+    #[test]
+    fn lines_of_other_shapes() {
+        assert_eq!(BoardShape::default().lines().count(), 8);
+        let shape = BoardShape {
+            size: 5,
+            win_length: 4,
+        };
+        assert_eq!(shape.lines().count(), 28);
+
+        let mut board = Board::empty(shape);
+        for i in 0..3 {
+            board.elements[i + 1][i] = Element::State(Some(State::Circle));
+        }
+        board.elements[3][3] = Element::State(Some(State::Cross));
+        assert_eq!(board.get_winner(), None);
+        board.elements[4][3] = Element::State(Some(State::Circle));
+        assert_eq!(board.get_winner(), Some(State::Circle));
+        assert_eq!(
+            board.winning_line(),
+            Some(vec![(1, 0), (2, 1), (3, 2), (4, 3)])
+        );
+    }
+
+    #[test]
+    fn boards_with_different_shapes_per_layer() {
+        let shapes = [
+            BoardShape {
+                size: 4,
+                win_length: 3,
+            },
+            BoardShape::default(),
+        ];
+        let board = Board::with_shapes(&shapes);
+        assert_eq!(board.size(), 4);
+        assert_eq!(board.layer_sizes(), vec![4, 3]);
+        assert_eq!(board.count_empty_cells(), 16 * 9);
+        assert_eq!(board.leaf_grid().len(), 12);
+    }
+
+    /// A full board without a line, circle owns five cells
+    fn drawn_board() -> Board {
+        let mut board = Board::default();
+        for (index, cell) in "XOOOXXOXO".chars().enumerate() {
+            let state = if cell == 'O' {
+                State::Circle
+            } else {
+                State::Cross
+            };
+            board.elements[index % 3][index / 3] = Element::State(Some(state));
+        }
+        board
+    }
+
+    #[test]
+    fn draw_policies() {
+        for (player, both, majority) in [
+            (State::Circle, Some(State::Circle), Some(State::Circle)),
+            (State::Cross, Some(State::Cross), None),
+        ] {
+            let mut board = Board::default();
+            board.elements[0][0] = Element::State(Some(player));
+            board.elements[1][0] = Element::Board(Box::new(drawn_board()));
+            board.elements[2][0] = Element::State(Some(player));
+
+            assert_eq!(board.get_winner_with(DrawPolicy::Dead), None);
+            assert_eq!(board.get_winner_with(DrawPolicy::CountsForBoth), both);
+            assert_eq!(board.get_winner_with(DrawPolicy::MajorityWins), majority);
+        }
+
+        // drawn boards alone don't make a line for both players
+        let mut board = Board::default();
+        for x in 0..3 {
+            board.elements[x][0] = Element::Board(Box::new(drawn_board()));
+        }
+        assert_eq!(board.get_winner_with(DrawPolicy::CountsForBoth), None);
+        assert_eq!(
+            board.get_winner_with(DrawPolicy::MajorityWins),
+            Some(State::Circle)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json_shape() {
+        let mut board = Board::default();
+        board.elements[0][0] = Element::State(Some(State::Circle));
+        board.elements[1][0] = Element::Board(Box::default());
+        if let Element::Board(inner) = &mut board.elements[1][0] {
+            inner.elements[2][2] = Element::State(Some(State::Cross));
+        }
+
+        let json = serde_json::to_value(&board).unwrap();
+        assert_eq!(
+            json["elements"][0][0],
+            serde_json::json!({ "state": "circle" })
+        );
+        assert_eq!(json["elements"][0][1], serde_json::json!({ "state": null }));
+        assert_eq!(
+            json["elements"][1][0]["board"]["elements"][2][2],
+            serde_json::json!({ "state": "cross" })
+        );
+
+        let deserialized: Board = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.to_string(), board.to_string());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_checks_shapes() {
+        let empty = serde_json::json!({ "state": null });
+        let column = serde_json::json!([empty, empty, empty]);
+        // boards from before there were other shapes have no win length
+        let board: Board =
+            serde_json::from_value(serde_json::json!({ "elements": [column, column, column] }))
+                .unwrap();
+        assert_eq!(board.shape(), BoardShape::default());
+
+        for json in [
+            serde_json::json!({ "elements": [[empty, empty, empty, empty], [empty], [empty]] }),
+            serde_json::json!({ "elements": [] }),
+            serde_json::json!({ "elements": [column, column, column], "win_length": 4 }),
+            serde_json::json!({ "elements": [column, column, column], "win_length": 0 }),
+        ] {
+            assert!(serde_json::from_value::<Board>(json).is_err());
+        }
+    }
 }
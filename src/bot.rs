@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use crate::{Game, Move};
+
+/// A computer player
+pub trait Bot: Send {
+    fn name(&self) -> String;
+
+    /// Picks a move for the current player, returns `None` if there are no legal moves
+    fn choose_move(&mut self, game: &Game) -> Option<Move>;
+
+    /// Sets how long the bot may think about a move, bots without a time limit can ignore this
+    fn set_time_limit(&mut self, _time_limit: Duration) {}
+
+    /// Information about how the last move was chosen, to show to the user
+    fn report(&self) -> Option<String> {
+        None
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{Board, BoardShape, Element};
+
+/// The position of an element in a `Board`, as a list of (x, y) indices starting from the root board
+///
+/// Displays as cell indices separated by dots (`"4.0.8"`), where an index is `x + y * 3`,
+/// or with the alternate flag (`{:#}`) as a column letter and row number separated by slashes (`"b2/a1"`).
+/// Cell indices only exist for the cells of a three by three board, a path through any other cell is always
+/// displayed with letters and numbers. The root board is displayed as `"-"`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct CellPath(pub Vec<(usize, usize)>);
+
+impl CellPath {
+    pub fn root() -> Self {
+        Self(vec![])
+    }
+
+    pub fn child(&self, x: usize, y: usize) -> Self {
+        let mut path = self.clone();
+        path.0.push((x, y));
+        path
+    }
+
+    pub fn parent(&self) -> Option<Self> {
+        let (_, parent) = self.0.split_last()?;
+        Some(Self(parent.to_vec()))
+    }
+
+    /// Returns the center and size of the element at this path in `board`, in the space that the board is rendered in
+    ///
+    /// The root board is [`BOARD_SIZE`] wide whatever its number of cells
+    pub fn world_rect(&self, board: &Board) -> (cgmath::Vector2<f32>, cgmath::Vector2<f32>) {
+        let mut position = cgmath::vec2(0.0, 0.0);
+        let mut scale = cgmath::vec2(BOARD_SIZE, BOARD_SIZE);
+        let mut board = Some(board);
+        for &(x, y) in &self.0 {
+            let Some(current) = board else {
+                break;
+            };
+            scale /= current.size() as f32;
+            position += cell_offset(current.size(), (x, y), scale);
+            board = match current.elements.get(x).and_then(|column| column.get(y)) {
+                Some(Element::Board(inner) | Element::Won { board: inner, .. }) => Some(inner),
+                _ => None,
+            };
+        }
+        (position, scale)
+    }
+
+    /// Returns the path of the cell in `board` containing `position`
+    pub fn from_world_position(board: &Board, position: cgmath::Vector2<f32>) -> Option<Self> {
+        let mut path = Self::root();
+        let mut board = board;
+        let mut board_position = cgmath::vec2(0.0, 0.0);
+        let mut scale = cgmath::vec2(BOARD_SIZE, BOARD_SIZE) / board.size() as f32;
+        'layers: loop {
+            for (x, column) in board.elements.iter().enumerate() {
+                for (y, element) in column.iter().enumerate() {
+                    let cell_position = board_position + cell_offset(board.size(), (x, y), scale);
+                    if (position.x - cell_position.x).abs() > scale.x * 0.5
+                        || (position.y - cell_position.y).abs() > scale.y * 0.5
+                    {
+                        continue;
+                    }
+
+                    path.0.push((x, y));
+                    match element {
+                        Element::State(_) | Element::Won { .. } => return Some(path),
+                        Element::Board(inner) => {
+                            board = inner;
+                            board_position = cell_position;
+                            scale /= board.size() as f32;
+                            continue 'layers;
+                        }
+                    }
+                }
+            }
+            return None;
+        }
+    }
+}
+
+/// The width of the root board when rendered
+pub const BOARD_SIZE: f32 = 3.0;
+
+/// The offset of the center of cell `(x, y)` from the center of a board with `size` cells of size `scale`
+pub(crate) fn cell_offset(
+    size: usize,
+    (x, y): (usize, usize),
+    scale: cgmath::Vector2<f32>,
+) -> cgmath::Vector2<f32> {
+    let center = (size as f32 - 1.0) / 2.0;
+    cgmath::vec2((x as f32 - center) * scale.x, (y as f32 - center) * scale.y)
+}
+
+impl std::ops::Deref for CellPath {
+    type Target = [(usize, usize)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Vec<(usize, usize)>> for CellPath {
+    fn from(path: Vec<(usize, usize)>) -> Self {
+        Self(path)
+    }
+}
+
+impl std::fmt::Display for CellPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "-");
+        }
+        let letters = f.alternate() || self.0.iter().any(|&(x, y)| x >= 3 || y >= 3);
+        for (i, &(x, y)) in self.0.iter().enumerate() {
+            if letters {
+                if i > 0 {
+                    write!(f, "/")?;
+                }
+                write!(f, "{}{}", (b'a' + x as u8) as char, y + 1)?;
+            } else {
+                if i > 0 {
+                    write!(f, ".")?;
+                }
+                write!(f, "{}", x + y * 3)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseCellPathError {
+    Empty,
+    InvalidCell(String),
+}
+
+impl std::fmt::Display for ParseCellPathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseCellPathError::Empty => write!(f, "the cell path is empty"),
+            ParseCellPathError::InvalidCell(cell) => write!(f, "'{cell}' is not a valid cell"),
+        }
+    }
+}
+
+impl std::error::Error for ParseCellPathError {}
+
+impl std::str::FromStr for CellPath {
+    type Err = ParseCellPathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(ParseCellPathError::Empty);
+        }
+        if s == "-" {
+            return Ok(Self::root());
+        }
+
+        let invalid = |cell: &str| ParseCellPathError::InvalidCell(cell.to_string());
+        if s.contains(|c: char| c.is_ascii_alphabetic()) {
+            s.split('/')
+                .map(|cell| {
+                    let mut chars = cell.chars();
+                    let x = match chars.next() {
+                        Some(c @ 'a'..='z') => c as usize - 'a' as usize,
+                        _ => return Err(invalid(cell)),
+                    };
+                    let y = match chars.as_str().parse::<usize>() {
+                        Ok(y @ 1..=BoardShape::MAX_SIZE) => y - 1,
+                        _ => return Err(invalid(cell)),
+                    };
+                    Ok((x, y))
+                })
+                .collect::<Result<_, _>>()
+                .map(Self)
+        } else {
+            s.split('.')
+                .map(|cell| match cell.parse::<usize>() {
+                    Ok(index @ 0..=8) => Ok((index % 3, index / 3)),
+                    _ => Err(invalid(cell)),
+                })
+                .collect::<Result<_, _>>()
+                .map(Self)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_and_parse() {
+        let path = CellPath(vec![(1, 1), (0, 0), (2, 2)]);
+        assert_eq!(path.to_string(), "4.0.8");
+        assert_eq!(format!("{path:#}"), "b2/a1/c3");
+        assert_eq!("4.0.8".parse(), Ok(path.clone()));
+        assert_eq!("b2/a1/c3".parse(), Ok(path));
+        assert_eq!("-".parse(), Ok(CellPath::root()));
+        assert_eq!(
+            "4.9".parse::<CellPath>(),
+            Err(ParseCellPathError::InvalidCell("9".into()))
+        );
+        assert_eq!(
+            "b2/d27".parse::<CellPath>(),
+            Err(ParseCellPathError::InvalidCell("d27".into()))
+        );
+        let path = CellPath(vec![(1, 1), (3, 0)]);
+        assert_eq!(path.to_string(), "b2/d1");
+        assert_eq!("b2/d1".parse(), Ok(path));
+        assert_eq!("".parse::<CellPath>(), Err(ParseCellPathError::Empty));
+    }
+
+    #[test]
+    fn world_rect_round_trip() {
+        let board = Board::new(3);
+        let path = CellPath(vec![(2, 0), (1, 2), (0, 1)]);
+        let (position, size) = path.world_rect(&board);
+        assert!((size.x - 1.0 / 9.0).abs() < 1e-6);
+        assert_eq!(CellPath::from_world_position(&board, position), Some(path));
+        assert_eq!(
+            CellPath::from_world_position(&board, cgmath::vec2(2.0, 0.0)),
+            None
+        );
+
+        let shapes = [
+            BoardShape {
+                size: 4,
+                win_length: 3,
+            },
+            BoardShape {
+                size: 5,
+                win_length: 4,
+            },
+        ];
+        let board = Board::with_shapes(&shapes);
+        let path = CellPath(vec![(3, 0), (4, 2)]);
+        let (position, size) = path.world_rect(&board);
+        assert!((size.x - 3.0 / 20.0).abs() < 1e-6);
+        assert_eq!(CellPath::from_world_position(&board, position), Some(path));
+    }
+}
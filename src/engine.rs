@@ -0,0 +1,552 @@
+//! A line based text protocol for talking to bots in other processes, in the spirit of UCI
+//!
+//! The host writes commands to the engine's stdin, and the engine answers on its stdout:
+//!
+//! - `uttt` starts the session, the engine answers with `id name <name>` and then `utttok`
+//! - `isready` the engine answers with `readyok` once it has processed all previous commands
+//! - `newgame layers=<n> placement=<free|sent-to> [draws=<dead|both|majority>] [misere=<off|top|all>]
+//!   [boards=<size>:<in a row>,...]` starts a new game, `boards` lists the board shapes from the outermost layer in.
+//!   Games with more layers or cells than [`Rules::within_limits`] allows are refused
+//! - `position [start <position>] moves <cell> ...` sets up the current position, as the moves played from
+//!   the start position (in position notation, an empty board if left out)
+//! - `go movetime=<ms>` asks for a move, the engine answers with `bestmove <cell>` or `bestmove none`
+//! - `quit` ends the session
+//!
+//! An engine can send `info <text>` lines at any time, they are shown to the user.
+//! Cells are written as cell paths, and unknown commands are ignored.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{Bot, CellPath, Game, Move, Rules};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostCommand {
+    Uttt,
+    IsReady,
+    NewGame {
+        num_layers: usize,
+        rules: Rules,
+    },
+    Position {
+        start_position: Option<String>,
+        moves: Vec<Move>,
+    },
+    Go {
+        movetime: Duration,
+    },
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineMessage {
+    Id { name: String },
+    UtttOk,
+    ReadyOk,
+    BestMove(Option<Move>),
+    Info(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMessageError(pub String);
+
+impl std::fmt::Display for ParseMessageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid message '{}'", self.0)
+    }
+}
+
+impl std::error::Error for ParseMessageError {}
+
+impl std::fmt::Display for HostCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostCommand::Uttt => write!(f, "uttt"),
+            HostCommand::IsReady => write!(f, "isready"),
+            HostCommand::NewGame { num_layers, rules } => {
+                write!(f, "newgame layers={num_layers}")?;
+                for (name, value) in rules.options() {
+                    write!(f, " {name}={value}")?;
+                }
+                Ok(())
+            }
+            HostCommand::Position {
+                start_position,
+                moves,
+            } => {
+                write!(f, "position")?;
+                if let Some(start_position) = start_position {
+                    write!(f, " start {start_position}")?;
+                }
+                write!(f, " moves")?;
+                for mv in moves {
+                    write!(f, " {}", mv.path)?;
+                }
+                Ok(())
+            }
+            HostCommand::Go { movetime } => write!(f, "go movetime={}", movetime.as_millis()),
+            HostCommand::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+fn parse_moves<'a>(
+    paths: impl Iterator<Item = &'a str>,
+    line: &str,
+) -> Result<Vec<Move>, ParseMessageError> {
+    paths
+        .map(|path| {
+            path.parse::<CellPath>()
+                .map(|path| Move { path })
+                .map_err(|_| ParseMessageError(line.to_string()))
+        })
+        .collect()
+}
+
+impl std::str::FromStr for HostCommand {
+    type Err = ParseMessageError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseMessageError(line.to_string());
+        let mut words = line.split_whitespace();
+        Ok(match words.next().ok_or_else(invalid)? {
+            "uttt" => HostCommand::Uttt,
+            "isready" => HostCommand::IsReady,
+            "quit" => HostCommand::Quit,
+            "newgame" => {
+                let mut num_layers = None;
+                let mut rules = Rules::default();
+                for option in words {
+                    match option.split_once('=').ok_or_else(invalid)? {
+                        ("layers", layers) => {
+                            num_layers = Some(layers.parse().map_err(|_| invalid())?)
+                        }
+                        (name, value) => {
+                            rules.set_option(name, value).map_err(|_| invalid())?;
+                        }
+                    }
+                }
+                HostCommand::NewGame {
+                    num_layers: num_layers
+                        .filter(|&layers| rules.within_limits(layers))
+                        .ok_or_else(invalid)?,
+                    rules,
+                }
+            }
+            "position" => {
+                let rest = words.collect::<Vec<_>>();
+                let moves_start = rest
+                    .iter()
+                    .position(|&word| word == "moves")
+                    .unwrap_or(rest.len());
+                let start_position = match rest[..moves_start] {
+                    [] => None,
+                    ["start", ref position @ ..] if !position.is_empty() => {
+                        Some(position.join(" "))
+                    }
+                    _ => return Err(invalid()),
+                };
+                let moves = parse_moves(rest.iter().skip(moves_start + 1).copied(), line)?;
+                HostCommand::Position {
+                    start_position,
+                    moves,
+                }
+            }
+            "go" => {
+                let mut movetime = Duration::from_secs(1);
+                for option in words {
+                    if let ("movetime", millis) = option.split_once('=').ok_or_else(invalid)? {
+                        movetime = Duration::from_millis(millis.parse().map_err(|_| invalid())?);
+                    }
+                }
+                HostCommand::Go { movetime }
+            }
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+impl std::fmt::Display for EngineMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineMessage::Id { name } => write!(f, "id name {name}"),
+            EngineMessage::UtttOk => write!(f, "utttok"),
+            EngineMessage::ReadyOk => write!(f, "readyok"),
+            EngineMessage::BestMove(Some(mv)) => write!(f, "bestmove {}", mv.path),
+            EngineMessage::BestMove(None) => write!(f, "bestmove none"),
+            EngineMessage::Info(text) => write!(f, "info {text}"),
+        }
+    }
+}
+
+impl std::str::FromStr for EngineMessage {
+    type Err = ParseMessageError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseMessageError(line.to_string());
+        let line = line.trim();
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        Ok(match keyword {
+            "utttok" => EngineMessage::UtttOk,
+            "readyok" => EngineMessage::ReadyOk,
+            "info" => EngineMessage::Info(rest.to_string()),
+            "id" => match rest.split_once(' ') {
+                Some(("name", name)) => EngineMessage::Id {
+                    name: name.to_string(),
+                },
+                _ => return Err(invalid()),
+            },
+            "bestmove" => match rest.trim() {
+                "none" => EngineMessage::BestMove(None),
+                path => EngineMessage::BestMove(Some(
+                    parse_moves(std::iter::once(path), line)?
+                        .pop()
+                        .ok_or_else(invalid)?,
+                )),
+            },
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+/// Runs `bot` as an engine, reading commands from `input` and answering on `output` until `quit` or the end of input
+pub fn run_engine(
+    bot: &mut dyn Bot,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> std::io::Result<()> {
+    let mut new_game = (2, Rules::default());
+    let mut game = Game::new(new_game.0, new_game.1.clone());
+
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match line.parse::<HostCommand>() {
+            Ok(command) => command,
+            Err(error) => {
+                writeln!(output, "{}", EngineMessage::Info(error.to_string()))?;
+                output.flush()?;
+                continue;
+            }
+        };
+
+        match command {
+            HostCommand::Uttt => {
+                writeln!(output, "{}", EngineMessage::Id { name: bot.name() })?;
+                writeln!(output, "{}", EngineMessage::UtttOk)?;
+            }
+            HostCommand::IsReady => writeln!(output, "{}", EngineMessage::ReadyOk)?,
+            HostCommand::NewGame { num_layers, rules } => {
+                new_game = (num_layers, rules.clone());
+                game = Game::new(num_layers, rules);
+            }
+            HostCommand::Position {
+                start_position,
+                moves,
+            } => {
+                let start = match start_position {
+                    Some(start_position) => {
+                        Game::from_notation(&start_position, new_game.1.clone())
+                            .map_err(|error| error.to_string())
+                    }
+                    None => Ok(Game::new(new_game.0, new_game.1.clone())),
+                };
+                let position = start.and_then(|mut start| {
+                    for mv in moves {
+                        start.play(mv).map_err(|error| error.to_string())?;
+                    }
+                    Ok(start)
+                });
+                match position {
+                    Ok(position) => game = position,
+                    Err(error) => writeln!(output, "{}", EngineMessage::Info(error))?,
+                }
+            }
+            HostCommand::Go { movetime } => {
+                bot.set_time_limit(movetime);
+                let mv = bot.choose_move(&game);
+                if let Some(report) = bot.report() {
+                    for line in report.lines() {
+                        writeln!(output, "{}", EngineMessage::Info(line.to_string()))?;
+                    }
+                }
+                writeln!(output, "{}", EngineMessage::BestMove(mv))?;
+            }
+            HostCommand::Quit => break,
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// The host side of a connection to an engine
+pub struct EngineConnection<R, W> {
+    reader: R,
+    writer: W,
+    name: String,
+    info: Vec<String>,
+}
+
+impl<R: BufRead, W: Write> EngineConnection<R, W> {
+    /// Starts the session, waiting for the engine to identify itself
+    pub fn new(reader: R, writer: W) -> std::io::Result<Self> {
+        let mut connection = Self {
+            reader,
+            writer,
+            name: "Engine".into(),
+            info: vec![],
+        };
+        connection.send(&HostCommand::Uttt)?;
+        loop {
+            match connection.receive()? {
+                EngineMessage::Id { name } => connection.name = name,
+                EngineMessage::UtttOk => break,
+                _ => {}
+            }
+        }
+        Ok(connection)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The info lines the engine sent while choosing its last move
+    pub fn info(&self) -> &[String] {
+        &self.info
+    }
+
+    pub fn send(&mut self, command: &HostCommand) -> std::io::Result<()> {
+        writeln!(self.writer, "{command}")?;
+        self.writer.flush()
+    }
+
+    /// Reads the next message, skipping lines that aren't part of the protocol
+    pub fn receive(&mut self) -> std::io::Result<EngineMessage> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            if let Ok(message) = line.parse() {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Asks the engine for a move in the position of `game`
+    pub fn best_move(&mut self, game: &Game, movetime: Duration) -> std::io::Result<Option<Move>> {
+        self.send(&HostCommand::NewGame {
+            num_layers: game.num_layers(),
+            rules: game.rules().clone(),
+        })?;
+        self.send(&HostCommand::Position {
+            start_position: game.start_position().map(str::to_string),
+            moves: game.moves().cloned().collect(),
+        })?;
+        self.send(&HostCommand::Go { movetime })?;
+
+        self.info.clear();
+        loop {
+            match self.receive()? {
+                EngineMessage::BestMove(mv) => return Ok(mv),
+                EngineMessage::Info(info) => self.info.push(info),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Kills `child` unless the returned sender is dropped before `deadline` has passed
+fn watchdog(child: &Arc<Mutex<Child>>, deadline: Duration) -> mpsc::Sender<()> {
+    let child = child.clone();
+    let (sender, receiver) = mpsc::channel::<()>();
+    std::thread::spawn(move || {
+        if receiver.recv_timeout(deadline) == Err(mpsc::RecvTimeoutError::Timeout) {
+            _ = child.lock().unwrap().kill();
+        }
+    });
+    sender
+}
+
+/// A bot running in another process, that is talked to with the engine protocol
+///
+/// An engine that doesn't answer in time is killed, so waiting for it never blocks for long
+pub struct EngineBot {
+    child: Arc<Mutex<Child>>,
+    connection: EngineConnection<BufReader<ChildStdout>, ChildStdin>,
+    pub movetime: Duration,
+    error: Option<String>,
+}
+
+impl EngineBot {
+    /// How long an engine has to identify itself after being started
+    pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+    /// How much longer than the requested movetime an engine may take before it is killed
+    pub const MOVE_GRACE_TIME: Duration = Duration::from_secs(2);
+
+    /// Starts the engine executable at `path`, split on whitespace to allow passing arguments
+    pub fn spawn(command: &str, movetime: Duration) -> std::io::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "no command"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let reader = BufReader::new(child.stdout.take().unwrap());
+        let writer = child.stdin.take().unwrap();
+        let child = Arc::new(Mutex::new(child));
+        let watchdog = watchdog(&child, Self::HANDSHAKE_TIMEOUT);
+        let connection = match EngineConnection::new(reader, writer) {
+            Ok(connection) => connection,
+            Err(error) => {
+                let mut child = child.lock().unwrap();
+                _ = child.kill();
+                _ = child.wait();
+                return Err(error);
+            }
+        };
+        drop(watchdog);
+        Ok(Self {
+            child,
+            connection,
+            movetime,
+            error: None,
+        })
+    }
+}
+
+impl Bot for EngineBot {
+    fn name(&self) -> String {
+        self.connection.name().to_string()
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Move> {
+        let watchdog = watchdog(&self.child, self.movetime + Self::MOVE_GRACE_TIME);
+        let result = self.connection.best_move(game, self.movetime);
+        drop(watchdog);
+        match result {
+            Ok(mv) => {
+                self.error = None;
+                mv
+            }
+            Err(error) => {
+                self.error = Some(format!("the engine stopped responding: {error}"));
+                None
+            }
+        }
+    }
+
+    fn set_time_limit(&mut self, time_limit: Duration) {
+        self.movetime = time_limit;
+    }
+
+    fn report(&self) -> Option<String> {
+        match &self.error {
+            Some(error) => Some(error.clone()),
+            None if self.connection.info().is_empty() => None,
+            None => Some(self.connection.info().join("\n")),
+        }
+    }
+}
+
+impl Drop for EngineBot {
+    fn drop(&mut self) {
+        _ = self.connection.send(&HostCommand::Quit);
+        let mut child = self.child.lock().unwrap();
+        _ = child.kill();
+        _ = child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MinimaxBot;
+
+    #[test]
+    fn commands_round_trip() {
+        for line in [
+            "uttt",
+            "isready",
+            "newgame layers=3 placement=sent-to",
+            "position moves",
+            "position moves 4.0 0.8",
+            "position start [9][9][9][9][O8][9][9][9][9] X 0 1 moves 0.4",
+            "go movetime=250",
+            "quit",
+        ] {
+            assert_eq!(line.parse::<HostCommand>().unwrap().to_string(), line);
+        }
+        assert!("newgame layers=0".parse::<HostCommand>().is_err());
+        assert!("newgame layers=12".parse::<HostCommand>().is_err());
+        assert!("newgame layers=3 boards=26".parse::<HostCommand>().is_err());
+        assert!("position moves 4.9".parse::<HostCommand>().is_err());
+        assert!("position start moves".parse::<HostCommand>().is_err());
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        for line in [
+            "id name Some Bot",
+            "utttok",
+            "readyok",
+            "bestmove 4.0.8",
+            "bestmove none",
+            "info depth 3",
+        ] {
+            assert_eq!(line.parse::<EngineMessage>().unwrap().to_string(), line);
+        }
+    }
+
+    #[test]
+    fn host_talks_to_engine() {
+        let (host_reader, engine_writer) = std::io::pipe().unwrap();
+        let (engine_reader, host_writer) = std::io::pipe().unwrap();
+        let engine = std::thread::spawn(move || {
+            let mut bot = MinimaxBot::default();
+            run_engine(&mut bot, BufReader::new(engine_reader), engine_writer).unwrap();
+        });
+
+        let mut connection =
+            EngineConnection::new(BufReader::new(host_reader), host_writer).unwrap();
+        assert_eq!(connection.name(), MinimaxBot::default().name());
+
+        let game = Game::from_notation("OO1XX4 O", Rules::default()).unwrap();
+        let mv = connection
+            .best_move(&game, Duration::from_millis(500))
+            .unwrap();
+        assert_eq!(mv.map(|mv| mv.path.to_string()), Some("2".into()));
+
+        let mut game = Game::new(2, Rules::default());
+        game.play(Move {
+            path: "4.4".parse().unwrap(),
+        })
+        .unwrap();
+        let mv = connection
+            .best_move(&game, Duration::from_millis(100))
+            .unwrap()
+            .unwrap();
+        assert!(game.check_move(&mv).is_ok());
+
+        connection.send(&HostCommand::Quit).unwrap();
+        engine.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn silent_engines_are_killed() {
+        let start = std::time::Instant::now();
+        assert!(EngineBot::spawn("sleep 60", Duration::from_millis(100)).is_err());
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,596 @@
+use rand::Rng;
+
+use crate::{
+    active_board_key, cell_key, won_key, Board, CellPath, Element, GameState, Rules, State,
+    CROSS_TO_MOVE_KEY,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub path: CellPath,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ongoing,
+    Won(State),
+    Stalemate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    GameOver,
+    InvalidCell,
+    Occupied,
+    OutsideActiveBoard,
+}
+
+impl std::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "the game is already over"),
+            MoveError::InvalidCell => write!(f, "the move does not point to a cell on the board"),
+            MoveError::Occupied => write!(f, "the cell has already been played"),
+            MoveError::OutsideActiveBoard => {
+                write!(
+                    f,
+                    "the move is not inside of the board that has to be played"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    mv: Move,
+    /// The sub-boards that were won by the move, from the outermost one in
+    collapsed: Vec<CellPath>,
+    active_board: CellPath,
+}
+
+#[derive(Debug, Clone)]
+pub struct Game {
+    board: Board,
+    rules: Rules,
+    num_layers: usize,
+    turn: State,
+    active_board: CellPath,
+    num_moves: usize,
+    /// The winners and empty cells of every board, updated with every move instead of looking at the whole board
+    game_state: GameState,
+    history: Vec<HistoryEntry>,
+    redo_stack: Vec<Move>,
+    /// The notation of the position the game was started from, if it wasnt an empty board
+    start_position: Option<String>,
+    /// The zobrist hash of the position, updated with every move
+    hash: u64,
+}
+
+impl Game {
+    pub fn new(num_layers: usize, rules: Rules) -> Self {
+        let board = Board::with_shapes(&rules.layer_shapes(num_layers));
+        let game_state = GameState::new(&board, rules.draw_policy, rules.misere);
+        Self {
+            board,
+            rules,
+            num_layers,
+            turn: State::Circle,
+            active_board: CellPath::root(),
+            num_moves: 0,
+            game_state,
+            history: vec![],
+            redo_stack: vec![],
+            start_position: None,
+            hash: 0,
+        }
+    }
+
+    /// Starts a game from an arbitrary position, any sub-boards that have been won get collapsed
+    pub fn from_position(
+        mut board: Board,
+        turn: State,
+        active_board: CellPath,
+        num_moves: usize,
+        rules: Rules,
+    ) -> Self {
+        board.collapse_states_with(rules.draw_policy, rules.misere);
+        let num_layers = board.depth();
+        let game_state = GameState::new(&board, rules.draw_policy, rules.misere);
+        let mut game = Self {
+            board,
+            rules,
+            num_layers,
+            turn,
+            active_board,
+            num_moves,
+            game_state,
+            history: vec![],
+            redo_stack: vec![],
+            start_position: None,
+            hash: 0,
+        };
+        game.hash = game.board.zobrist_hash() ^ active_board_key(&game.active_board);
+        if turn == State::Cross {
+            game.hash ^= CROSS_TO_MOVE_KEY;
+        }
+        game.start_position = Some(game.to_notation());
+        game
+    }
+
+    /// The zobrist hash of the position, made of the board, the side to move and the board that has to be played in
+    pub fn zobrist_hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn start_position(&self) -> Option<&str> {
+        self.start_position.as_deref()
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// How every board of the game is decided, including the ones that have been collapsed
+    pub fn game_state(&self) -> &GameState {
+        &self.game_state
+    }
+
+    pub fn num_layers(&self) -> usize {
+        self.num_layers
+    }
+
+    pub fn current_player(&self) -> State {
+        self.turn
+    }
+
+    /// The path of the board the next move has to be played in, an empty path means anywhere
+    pub fn active_board(&self) -> &CellPath {
+        &self.active_board
+    }
+
+    pub fn num_moves(&self) -> usize {
+        self.num_moves
+    }
+
+    pub fn num_moves_left(&self) -> usize {
+        self.game_state.count_empty_cells()
+    }
+
+    /// The moves that have been played so far, in order
+    pub fn moves(&self) -> impl Iterator<Item = &Move> {
+        self.history.iter().map(|entry| &entry.mv)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// The board that was won by the last move and collapsed into a single cell
+    pub fn last_collapsed(&self) -> Option<&CellPath> {
+        self.history.last()?.collapsed.first()
+    }
+
+    pub fn status(&self) -> Outcome {
+        if let Some(line_owner) = self.game_state.get_winner() {
+            Outcome::Won(self.rules.misere.game_winner(line_owner))
+        } else if self.game_state.is_stalemate() {
+            Outcome::Stalemate
+        } else {
+            Outcome::Ongoing
+        }
+    }
+
+    pub fn check_move(&self, mv: &Move) -> Result<(), MoveError> {
+        if self.status() != Outcome::Ongoing {
+            return Err(MoveError::GameOver);
+        }
+        match self.board.get_element(&mv.path) {
+            Some(Element::State(None)) => {}
+            Some(Element::State(Some(_)) | Element::Won { .. }) => return Err(MoveError::Occupied),
+            Some(Element::Board(_)) | None => return Err(MoveError::InvalidCell),
+        }
+        if !Rules::is_move_allowed(&self.active_board, &mv.path) {
+            return Err(MoveError::OutsideActiveBoard);
+        }
+        Ok(())
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        fn collect_empty_cells(element: &Element, path: &mut CellPath, moves: &mut Vec<Move>) {
+            match element {
+                Element::State(None) => moves.push(Move { path: path.clone() }),
+                Element::State(Some(_)) | Element::Won { .. } => {}
+                Element::Board(board) => {
+                    for (x, column) in board.elements.iter().enumerate() {
+                        for (y, element) in column.iter().enumerate() {
+                            path.0.push((x, y));
+                            collect_empty_cells(element, path, moves);
+                            path.0.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut moves = vec![];
+        if self.status() != Outcome::Ongoing {
+            return moves;
+        }
+        let mut path = self.active_board.clone();
+        if path.is_empty() {
+            for (x, column) in self.board.elements.iter().enumerate() {
+                for (y, element) in column.iter().enumerate() {
+                    path.0.push((x, y));
+                    collect_empty_cells(element, &mut path, &mut moves);
+                    path.0.pop();
+                }
+            }
+        } else if let Some(element) = self.board.get_element(&self.active_board) {
+            collect_empty_cells(element, &mut path, &mut moves);
+        }
+        moves
+    }
+
+    /// The number of moves [`Game::legal_moves`] lists, counted from the tracked empty cells
+    pub fn num_legal_moves(&self) -> usize {
+        if self.status() != Outcome::Ongoing {
+            return 0;
+        }
+        self.game_state.count_empty_cells_in(&self.active_board)
+    }
+
+    /// The move at `index` in [`Game::legal_moves`], found without listing the others
+    pub fn nth_legal_move(&self, index: usize) -> Option<Move> {
+        if index >= self.num_legal_moves() {
+            return None;
+        }
+        let path = self.game_state.nth_empty_cell(&self.active_board, index)?;
+        Some(Move { path })
+    }
+
+    /// A random legal move, picked from the tracked empty cells instead of listing all of them
+    pub fn random_move(&self, rng: &mut impl Rng) -> Option<Move> {
+        match self.num_legal_moves() {
+            0 => None,
+            count => self.nth_legal_move(rng.gen_range(0..count)),
+        }
+    }
+
+    pub fn play(&mut self, mv: Move) -> Result<Outcome, MoveError> {
+        let outcome = self.play_move(mv)?;
+        self.redo_stack.clear();
+        Ok(outcome)
+    }
+
+    /// Plays a move without clearing [`Game::redo`], for searches that play moves and take them back again
+    pub(crate) fn play_move(&mut self, mv: Move) -> Result<Outcome, MoveError> {
+        self.check_move(&mv)?;
+
+        let Some(Element::State(state)) = self.board.get_element_mut(&mv.path) else {
+            unreachable!("the move was checked to point to a cell");
+        };
+        *state = Some(self.turn);
+        self.game_state.set_cell(&mv.path, Some(self.turn));
+        self.hash ^= cell_key(&mv.path, self.turn);
+
+        // only the boards containing the played cell can have been won by this move
+        let collapsed: Vec<CellPath> = (1..mv.path.len())
+            .map(|len| CellPath(mv.path[..len].to_vec()))
+            .filter(|path| self.game_state.board_winner(path).is_some())
+            .collect();
+
+        // the inner boards have to be collapsed first, they can't be reached anymore after the outer ones
+        for path in collapsed.iter().rev() {
+            let element = self.board.get_element_mut(path).unwrap();
+            if let Element::Board(board) = std::mem::take(element) {
+                let winner = self
+                    .rules
+                    .misere
+                    .sub_board_winner(self.game_state.board_winner(path).unwrap());
+                self.hash ^= won_key(path, winner);
+                *element = Element::Won { winner, board };
+            }
+        }
+        self.history.push(HistoryEntry {
+            mv,
+            collapsed,
+            active_board: std::mem::take(&mut self.active_board),
+        });
+        let mv = &self.history.last().unwrap().mv;
+        self.active_board = self.rules.next_active_board(&self.game_state, &mv.path);
+        self.num_moves += 1;
+        self.turn = self.turn.opponent();
+        self.hash ^= active_board_key(&self.history.last().unwrap().active_board)
+            ^ active_board_key(&self.active_board)
+            ^ CROSS_TO_MOVE_KEY;
+
+        Ok(self.status())
+    }
+
+    /// Takes back the last move, returning it
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = self.take_back()?;
+        self.redo_stack.push(mv.clone());
+        Some(mv)
+    }
+
+    /// Takes back the last move without making it available to [`Game::redo`], for searches that play moves and
+    /// take them back again
+    pub(crate) fn take_back(&mut self) -> Option<Move> {
+        let HistoryEntry {
+            mv,
+            collapsed,
+            active_board,
+        } = self.history.pop()?;
+
+        // the outer boards have to be opened up first to reach the inner ones
+        for path in collapsed {
+            let element = self.board.get_element_mut(&path).unwrap();
+            if let Element::Won { winner, board } = std::mem::take(element) {
+                self.hash ^= won_key(&path, winner);
+                *element = Element::Board(board);
+            }
+        }
+        *self.board.get_element_mut(&mv.path).unwrap() = Element::State(None);
+        self.game_state.set_cell(&mv.path, None);
+
+        self.hash ^= active_board_key(&self.active_board)
+            ^ active_board_key(&active_board)
+            ^ CROSS_TO_MOVE_KEY;
+        self.active_board = active_board;
+        self.num_moves -= 1;
+        self.turn = self.turn.opponent();
+        self.hash ^= cell_key(&mv.path, self.turn);
+        Some(mv)
+    }
+
+    /// Plays the last move that was taken back, returning it
+    pub fn redo(&mut self) -> Option<Move> {
+        let mv = self.redo_stack.pop()?;
+        self.play_move(mv.clone())
+            .expect("a move that was undone should be playable again");
+        Some(mv)
+    }
+
+    /// Counts the move sequences of exactly `depth` moves that can be played from this position, games that end
+    /// earlier don't count. The game is left as it was
+    pub fn perft(&mut self, depth: usize) -> u64 {
+        fn count(game: &mut Game, depth: usize) -> u64 {
+            if depth == 0 {
+                return 1;
+            }
+            let moves = game.legal_moves();
+            if depth == 1 {
+                return moves.len() as u64;
+            }
+            moves
+                .into_iter()
+                .map(|mv| {
+                    game.play_move(mv).expect("a legal move should be playable");
+                    let nodes = count(game, depth - 1);
+                    game.take_back();
+                    nodes
+                })
+                .sum()
+        }
+
+        count(self, depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Misere, Placement};
+
+    fn play(game: &mut Game, path: &[(usize, usize)]) -> Result<Outcome, MoveError> {
+        game.play(Move {
+            path: CellPath(path.to_vec()),
+        })
+    }
+
+    #[test]
+    fn turns_alternate() {
+        let mut game = Game::new(1, Rules::default());
+        assert_eq!(game.current_player(), State::Circle);
+        assert_eq!(play(&mut game, &[(0, 0)]), Ok(Outcome::Ongoing));
+        assert_eq!(game.current_player(), State::Cross);
+        assert_eq!(game.num_moves(), 1);
+        assert_eq!(game.num_moves_left(), 8);
+        assert_eq!(game.legal_moves().len(), 8);
+    }
+
+    #[test]
+    fn invalid_moves_are_rejected() {
+        let mut game = Game::new(2, Rules::default());
+        assert_eq!(play(&mut game, &[(0, 0)]), Err(MoveError::InvalidCell));
+        assert_eq!(
+            play(&mut game, &[(3, 0), (0, 0)]),
+            Err(MoveError::InvalidCell)
+        );
+        assert_eq!(play(&mut game, &[(0, 0), (0, 0)]), Ok(Outcome::Ongoing));
+        assert_eq!(play(&mut game, &[(0, 0), (0, 0)]), Err(MoveError::Occupied));
+        assert_eq!(game.current_player(), State::Cross);
+    }
+
+    #[test]
+    fn sent_to_restricts_legal_moves() {
+        let mut game = Game::new(
+            2,
+            Rules {
+                placement: Placement::SentTo,
+                ..Default::default()
+            },
+        );
+        assert_eq!(game.legal_moves().len(), 81);
+        play(&mut game, &[(0, 0), (2, 1)]).unwrap();
+        assert_eq!(game.active_board(), &CellPath(vec![(2, 1)]));
+        assert_eq!(game.legal_moves().len(), 9);
+        assert_eq!(
+            play(&mut game, &[(1, 1), (1, 1)]),
+            Err(MoveError::OutsideActiveBoard)
+        );
+        assert!(play(&mut game, &[(2, 1), (0, 0)]).is_ok());
+    }
+
+    #[test]
+    fn game_is_won() {
+        let mut game = Game::new(1, Rules::default());
+        for path in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            play(&mut game, &[path]).unwrap();
+        }
+        assert_eq!(play(&mut game, &[(2, 0)]), Ok(Outcome::Won(State::Circle)));
+        assert_eq!(game.status(), Outcome::Won(State::Circle));
+        assert!(game.legal_moves().is_empty());
+        assert_eq!(play(&mut game, &[(2, 2)]), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn undo_restores_collapsed_board() {
+        let mut game = Game::new(2, Rules::default());
+        for path in [
+            [(0, 0), (0, 0)],
+            [(1, 1), (0, 0)],
+            [(0, 0), (1, 0)],
+            [(1, 1), (1, 0)],
+        ] {
+            play(&mut game, &path).unwrap();
+        }
+        let before = game.board().clone();
+
+        play(&mut game, &[(0, 0), (2, 0)]).unwrap();
+        assert!(matches!(
+            game.board().elements[0][0],
+            Element::Won {
+                winner: State::Circle,
+                ..
+            }
+        ));
+
+        assert_eq!(
+            game.undo(),
+            Some(Move {
+                path: CellPath(vec![(0, 0), (2, 0)])
+            })
+        );
+        assert_eq!(format!("{:?}", game.board()), format!("{before:?}"));
+        assert_eq!(game.current_player(), State::Circle);
+        assert_eq!(game.num_moves(), 4);
+        assert_eq!(game.num_moves_left(), 77);
+
+        assert!(game.redo().is_some());
+        assert!(matches!(
+            game.board().elements[0][0],
+            Element::Won {
+                winner: State::Circle,
+                ..
+            }
+        ));
+        assert!(!game.can_redo());
+    }
+
+    #[test]
+    fn undo_reopens_nested_won_boards() {
+        let mut game = Game::from_notation("[O[OOO6]O[OOO6][OO7]6]8 O", Rules::default()).unwrap();
+        let before = game.board().to_string();
+        game.play(Move {
+            path: "0.2.2".parse().unwrap(),
+        })
+        .unwrap();
+        assert_eq!(game.board().to_string(), "O[O[OOO6]O[OOO6]O[OOO6]6]8");
+        assert_eq!(game.last_collapsed(), Some(&CellPath(vec![(0, 0)])));
+        assert_eq!(game.num_moves_left(), 8);
+
+        game.undo();
+        assert_eq!(game.board().to_string(), before);
+        assert_eq!(game.legal_moves().len(), 21);
+    }
+
+    #[test]
+    fn playing_clears_redo() {
+        let mut game = Game::new(
+            2,
+            Rules {
+                placement: Placement::SentTo,
+                ..Default::default()
+            },
+        );
+        play(&mut game, &[(0, 0), (2, 1)]).unwrap();
+        game.undo();
+        assert!(game.active_board().is_empty());
+        assert!(game.can_redo());
+        play(&mut game, &[(1, 1), (1, 1)]).unwrap();
+        assert!(!game.can_redo());
+        assert_eq!(game.moves().count(), 1);
+    }
+
+    #[test]
+    fn misere_lines_lose() {
+        let rules = Rules {
+            misere: Misere::TopLevel,
+            ..Default::default()
+        };
+        let mut game = Game::new(1, rules);
+        for cell in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            assert_eq!(play(&mut game, &[cell]), Ok(Outcome::Ongoing));
+        }
+        assert_eq!(play(&mut game, &[(2, 0)]), Ok(Outcome::Won(State::Cross)));
+
+        let rules = Rules {
+            misere: Misere::AllLayers,
+            ..Default::default()
+        };
+        let mut game = Game::new(2, rules);
+        for (x, y) in [(0, 0), (1, 0), (2, 0)] {
+            play(&mut game, &[(0, 0), (x, y)]).unwrap();
+            if x < 2 {
+                play(&mut game, &[(1, 1), (x, y)]).unwrap();
+            }
+        }
+        assert!(matches!(
+            game.board().elements[0][0],
+            Element::Won {
+                winner: State::Cross,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn perft_counts() {
+        let mut game = Game::new(1, Rules::default());
+        let counts: Vec<u64> = (0..=7).map(|depth| game.perft(depth)).collect();
+        assert_eq!(counts, [1, 9, 72, 504, 3024, 15120, 54720, 148176]);
+
+        let mut game = Game::new(2, Rules::default());
+        assert_eq!(game.perft(3), 81 * 80 * 79);
+
+        let mut game = Game::new(
+            2,
+            Rules {
+                placement: Placement::SentTo,
+                ..Default::default()
+            },
+        );
+        play(&mut game, &[(0, 0), (2, 1)]).unwrap();
+        game.undo();
+        let before = game.to_notation();
+        assert_eq!(
+            (1..=3).map(|depth| game.perft(depth)).collect::<Vec<_>>(),
+            [81, 720, 6336]
+        );
+        assert_eq!(game.to_notation(), before);
+        assert!(game.can_redo());
+    }
+}
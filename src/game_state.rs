@@ -0,0 +1,309 @@
+//! Bookkeeping of how every board of a game is decided, kept up to date along the path of each move
+//!
+//! Finding the winner of a board from scratch looks at every cell below it, which adds up on deep boards when it
+//! happens after every move. Only the boards containing the played cell can change, so only those are looked at again
+
+use crate::{
+    board::{resolve_cells, Resolution},
+    Board, BoardShape, CellPath, DrawPolicy, Element, Misere, State,
+};
+
+#[derive(Debug, Clone)]
+struct BoardNode {
+    shape: BoardShape,
+    /// How each cell is decided as seen from this board, indexed by `x * size + y`
+    cells: Vec<Resolution>,
+    /// The nodes of the sub-boards, for the cells that are or were a board
+    sub_boards: Vec<Option<BoardNode>>,
+    /// The empty cells that can still be played, not counting the ones in won sub-boards
+    empty: usize,
+    /// How this board itself is decided, with the line owner as the winner
+    resolution: Resolution,
+}
+
+impl BoardNode {
+    fn new(board: &Board, policy: DrawPolicy) -> Self {
+        let mut cells = vec![];
+        let mut sub_boards = vec![];
+        let mut empty = 0;
+        for element in board.elements.iter().flatten() {
+            let (cell, sub_board) = match element {
+                Element::State(None) => {
+                    empty += 1;
+                    (Resolution::Open, None)
+                }
+                &Element::State(Some(state)) => (Resolution::Won(state), None),
+                Element::Board(board) => {
+                    let node = BoardNode::new(board, policy);
+                    empty += node.empty;
+                    (node.resolution, Some(node))
+                }
+                Element::Won { winner, board } => (
+                    Resolution::Won(*winner),
+                    Some(BoardNode::new(board, policy)),
+                ),
+            };
+            cells.push(cell);
+            sub_boards.push(sub_board);
+        }
+        let shape = board.shape();
+        Self {
+            shape,
+            resolution: resolve_cells(shape, &cells, policy),
+            cells,
+            sub_boards,
+            empty,
+        }
+    }
+
+    /// The number of empty cells this board adds to the board containing it
+    fn empty_as_cell(&self, cell: Resolution) -> usize {
+        match cell {
+            Resolution::Won(_) => 0,
+            Resolution::Open | Resolution::Drawn { .. } => self.empty,
+        }
+    }
+
+    fn set_cell(
+        &mut self,
+        path: &[(usize, usize)],
+        state: Option<State>,
+        policy: DrawPolicy,
+        misere: Misere,
+    ) {
+        let Some((&(x, y), rest)) = path.split_first() else {
+            return;
+        };
+        let index = x * self.shape.size + y;
+        if rest.is_empty() {
+            let old = std::mem::replace(
+                &mut self.cells[index],
+                state.map_or(Resolution::Open, Resolution::Won),
+            );
+            match (old, state) {
+                (Resolution::Open, Some(_)) => self.empty -= 1,
+                (Resolution::Won(_), None) => self.empty += 1,
+                _ => {}
+            }
+        } else {
+            let node = self.sub_boards[index]
+                .as_mut()
+                .expect("the path should lead through sub-boards");
+            let old_empty = node.empty_as_cell(self.cells[index]);
+            node.set_cell(rest, state, policy, misere);
+            // a sub-board goes to whoever misère gives it to once a line is completed in it
+            self.cells[index] = match node.resolution {
+                Resolution::Won(line_owner) => Resolution::Won(misere.sub_board_winner(line_owner)),
+                resolution => resolution,
+            };
+            self.empty = self.empty - old_empty + node.empty_as_cell(self.cells[index]);
+        }
+        self.resolution = resolve_cells(self.shape, &self.cells, policy);
+    }
+
+    /// Appends the path to the empty cell at `index` among the playable cells below this board
+    fn find_empty_cell(&self, mut index: usize, path: &mut Vec<(usize, usize)>) -> Option<()> {
+        for (i, (&cell, sub_board)) in self.cells.iter().zip(&self.sub_boards).enumerate() {
+            let empty = match sub_board {
+                Some(node) => node.empty_as_cell(cell),
+                None => (cell == Resolution::Open) as usize,
+            };
+            if index < empty {
+                path.push((i / self.shape.size, i % self.shape.size));
+                return match sub_board {
+                    Some(node) => node.find_empty_cell(index, path),
+                    None => Some(()),
+                };
+            }
+            index -= empty;
+        }
+        None
+    }
+
+    fn get(&self, path: &[(usize, usize)]) -> Option<&BoardNode> {
+        match path.split_first() {
+            None => Some(self),
+            Some((&(x, y), _)) if x >= self.shape.size || y >= self.shape.size => None,
+            Some((&(x, y), rest)) => self
+                .sub_boards
+                .get(x * self.shape.size + y)?
+                .as_ref()?
+                .get(rest),
+        }
+    }
+}
+
+/// The winners, stalemates and empty cells of a board and all of its sub-boards
+///
+/// Every change to a cell of the board has to be passed to [`GameState::set_cell`], which updates the boards
+/// containing that cell. Sub-boards that get won have to be collapsed on the board as well
+#[derive(Debug, Clone)]
+pub struct GameState {
+    root: BoardNode,
+    draw_policy: DrawPolicy,
+    misere: Misere,
+}
+
+impl GameState {
+    /// Looks at the whole of `board` once, which should already have its won sub-boards collapsed
+    pub fn new(board: &Board, draw_policy: DrawPolicy, misere: Misere) -> Self {
+        Self {
+            root: BoardNode::new(board, draw_policy),
+            draw_policy,
+            misere,
+        }
+    }
+
+    /// Sets the cell at `path` to `state`, updating only the boards that contain it
+    pub fn set_cell(&mut self, path: &[(usize, usize)], state: Option<State>) {
+        self.root
+            .set_cell(path, state, self.draw_policy, self.misere);
+    }
+
+    /// The number of empty cells outside of won sub-boards, the same as [`Board::count_empty_cells`]
+    pub fn count_empty_cells(&self) -> usize {
+        self.root.empty
+    }
+
+    /// The number of empty cells outside of won sub-boards in the board at `path`
+    pub fn count_empty_cells_in(&self, path: &[(usize, usize)]) -> usize {
+        self.root.get(path).map_or(0, |node| node.empty)
+    }
+
+    /// The empty cell at `index` among the ones counted by [`GameState::count_empty_cells_in`], in the same order
+    /// as [`crate::Game::legal_moves`]
+    pub fn nth_empty_cell(&self, path: &[(usize, usize)], index: usize) -> Option<CellPath> {
+        let mut cell = path.to_vec();
+        self.root.get(path)?.find_empty_cell(index, &mut cell)?;
+        Some(CellPath(cell))
+    }
+
+    /// The player with a line on the outermost board, the same as [`Board::get_winner_with`]
+    pub fn get_winner(&self) -> Option<State> {
+        self.board_winner(&[])
+    }
+
+    /// Whether the outermost board can't be won anymore, the same as [`Board::is_stalemate_with`]
+    pub fn is_stalemate(&self) -> bool {
+        matches!(self.root.resolution, Resolution::Drawn { .. })
+    }
+
+    /// Whether the board at `path` or a board containing it is won or drawn, the same as finding a closed board with
+    /// [`Element::is_closed_with`] on the way to it. Paths that don't lead to a board count as closed
+    pub fn is_closed(&self, path: &[(usize, usize)]) -> bool {
+        let mut node = &self.root;
+        for &(x, y) in path {
+            if x >= node.shape.size || y >= node.shape.size {
+                return true;
+            }
+            let index = x * node.shape.size + y;
+            match (node.cells[index], &node.sub_boards[index]) {
+                (Resolution::Open, Some(sub_board)) => node = sub_board,
+                _ => return true,
+            }
+        }
+        false
+    }
+
+    /// The player with a line on the board at `path`, even if it has been collapsed
+    pub fn board_winner(&self, path: &[(usize, usize)]) -> Option<State> {
+        match self.root.get(path)?.resolution {
+            Resolution::Won(line_owner) => Some(line_owner),
+            Resolution::Open | Resolution::Drawn { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::{Game, Outcome, Rules};
+
+    /// The status and empty cells of the game, looking at the whole board like before every move was tracked
+    fn full_scan(game: &Game) -> (Outcome, usize) {
+        let (board, rules) = (game.board(), game.rules());
+        let outcome = if let Some(winner) = rules.winner(board) {
+            Outcome::Won(winner)
+        } else if board.is_stalemate_with(rules.draw_policy) {
+            Outcome::Stalemate
+        } else {
+            Outcome::Ongoing
+        };
+        (outcome, board.count_empty_cells())
+    }
+
+    fn check_random_games(rules: Rules, num_layers: usize, seed: u64) {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let mut game = Game::new(num_layers, rules.clone());
+        while let Some(mv) = game.legal_moves().choose(&mut rng).cloned() {
+            game.play(mv).unwrap();
+            assert_eq!((game.status(), game.num_moves_left()), full_scan(&game));
+            if game.status() == Outcome::Ongoing {
+                let state = GameState::new(game.board(), rules.draw_policy, rules.misere);
+                let active_board = game.active_board();
+                let cells = (0..state.count_empty_cells_in(active_board))
+                    .map(|index| state.nth_empty_cell(active_board, index).unwrap())
+                    .collect::<Vec<_>>();
+                let moves = game.legal_moves();
+                assert_eq!(game.num_legal_moves(), moves.len());
+                assert_eq!(game.nth_legal_move(moves.len() - 1).as_ref(), moves.last());
+                let moves = moves.into_iter().map(|mv| mv.path);
+                assert_eq!(cells, moves.collect::<Vec<_>>());
+            }
+
+            // nothing is left for a full collapse to do
+            let mut collapsed = game.board().clone();
+            collapsed.collapse_states_with(rules.draw_policy, rules.misere);
+            assert_eq!(collapsed.to_string(), game.board().to_string());
+        }
+        while game.undo().is_some() {
+            assert_eq!((game.status(), game.num_moves_left()), full_scan(&game));
+        }
+        assert_eq!(game.num_moves_left(), game.board().count_empty_cells());
+    }
+
+    #[test]
+    fn matches_full_scans() {
+        for (seed, num_layers) in [(1, 1), (2, 2), (3, 2), (4, 3)] {
+            check_random_games(Rules::default(), num_layers, seed);
+        }
+        for draw_policy in DrawPolicy::ALL {
+            for misere in Misere::ALL {
+                let rules = Rules {
+                    draw_policy,
+                    misere,
+                    ..Default::default()
+                };
+                check_random_games(rules, 2, 5);
+            }
+        }
+        let rules = Rules {
+            shapes: vec!["4:3".parse().unwrap(), "2:2".parse().unwrap()],
+            ..Default::default()
+        };
+        check_random_games(rules, 3, 6);
+    }
+
+    #[test]
+    fn starts_from_positions() {
+        let game = Game::from_notation("[O[OOO6]O[OOO6][OO7]6]8 O", Rules::default()).unwrap();
+        assert_eq!((game.status(), game.num_moves_left()), full_scan(&game));
+        let state = GameState::new(game.board(), DrawPolicy::Dead, Misere::Normal);
+        let mut num_won = 0;
+        for x in 0..3 {
+            for y in 0..3 {
+                let path = [(0, 0), (x, y)];
+                let winner = match game.board().get_element(&path) {
+                    Some(Element::Board(board) | Element::Won { board, .. }) => board.get_winner(),
+                    _ => None,
+                };
+                assert_eq!(state.board_winner(&path), winner);
+                num_won += winner.is_some() as usize;
+            }
+        }
+        assert_eq!(num_won, 2);
+        assert_eq!(state.board_winner(&[(1, 1), (1, 1)]), None);
+    }
+}
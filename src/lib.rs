@@ -1,37 +1,109 @@
 #![deny(elided_lifetimes_in_paths)]
 
+mod bitboard;
 mod board;
+mod bot;
+mod cell_path;
+mod engine;
+mod game;
+mod game_state;
+mod mcts;
+mod minimax;
+mod network;
+mod notation;
 mod per_object_data;
+mod player;
+mod random;
 mod rendering;
+mod replay;
+mod rules;
+mod save;
+mod server;
+mod text;
+mod tournament;
 mod vertex;
+mod zobrist;
 
-use encase::ShaderType;
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
 
+pub use bitboard::*;
 pub use board::*;
+pub use bot::*;
+pub use cell_path::*;
+pub use engine::*;
+pub use game::*;
+pub use game_state::*;
+pub use mcts::*;
+pub use minimax::*;
+pub use network::*;
+pub use notation::*;
 pub use per_object_data::*;
+pub use player::*;
+pub use random::*;
 pub use rendering::*;
+pub use replay::*;
+pub use rules::*;
+pub use save::*;
+pub use server::*;
+pub use tournament::*;
 pub use vertex::*;
+pub use zobrist::*;
 
+pub use camera::Camera;
 use eframe::egui;
 
-#[derive(Clone, Copy, ShaderType)]
-pub struct Camera {
-    pub position: cgmath::Vector2<f32>,
-    pub screen_size: cgmath::Vector2<f32>,
-    pub rotation: f32,
-    pub scale: f32,
+// the field checks generated by the encase 0.4 derive are never called, which newer compilers report as dead code,
+// and the allow has to be on a module around the struct to reach them
+#[allow(dead_code)]
+mod camera {
+    use encase::ShaderType;
+
+    #[derive(Clone, Copy, ShaderType)]
+    pub struct Camera {
+        pub position: cgmath::Vector2<f32>,
+        pub screen_size: cgmath::Vector2<f32>,
+        pub rotation: f32,
+        pub scale: f32,
+    }
 }
 
 pub struct App {
     camera: Camera,
     last_frame_time: std::time::Instant,
-    board: Board,
-    turn: State,
+    game: Game,
     game_over: bool,
     num_layers: usize,
-    num_moves: usize,
-    num_moves_left: usize,
+    rules: Rules,
+    save_path: String,
+    position: String,
+    message: Option<String>,
+    players: [PlayerSettings; 2],
+    bots: [Option<Box<dyn Bot>>; 2],
+    thinking: Option<mpsc::Receiver<BotResult>>,
+    network: Option<NetworkSession>,
+    connecting: Option<Connecting>,
+    network_port: u16,
+    network_address: String,
+    replay: Option<Replay>,
+}
+
+/// A network game that is being hosted or joined in the background
+struct Connecting {
+    receiver: mpsc::Receiver<Result<(NetworkSession, Game), NetworkError>>,
+    /// The address the game is hosted on, connecting to it wakes up the waiting thread when hosting is cancelled
+    hosting: Option<std::net::SocketAddr>,
+}
+
+/// The bot is sent back along with its move, so it can be reused for the next move.
+/// Bots are started on the thinking thread as well, so an error starting one is sent back instead
+type BotResult = std::io::Result<(Box<dyn Bot>, Option<Move>)>;
+
+/// The index of the player of `state` in arrays holding something for both players
+pub(crate) fn side_index(state: State) -> usize {
+    match state {
+        State::Circle => 0,
+        State::Cross => 1,
+    }
 }
 
 impl App {
@@ -52,50 +124,285 @@ impl App {
             .paint_callback_resources
             .insert(render_state);
 
-        let mut app = Self {
+        let num_layers = 2;
+        let rules = Rules::default();
+        Self {
             camera,
             last_frame_time: std::time::Instant::now(),
-            board: Board::default(),
-            turn: State::Circle,
+            game: Game::new(num_layers, rules.clone()),
             game_over: false,
-            num_layers: 2,
-            num_moves: 0,
-            num_moves_left: 0,
-        };
-        app.restart();
-        app
+            num_layers,
+            rules,
+            save_path: "game.uttt".into(),
+            position: String::new(),
+            message: None,
+            players: Default::default(),
+            bots: Default::default(),
+            thinking: None,
+            network: None,
+            connecting: None,
+            network_port: 7878,
+            network_address: "127.0.0.1:7878".into(),
+            replay: None,
+        }
+    }
+
+    /// Has to be called whenever the game is changed by anything other than a move
+    fn game_changed(&mut self) {
+        self.game_over = self.game.status() != Outcome::Ongoing;
+        self.replay = None;
+        // the result would be for a different position
+        self.thinking = None;
     }
 
     fn restart(&mut self) {
-        self.turn = State::Circle;
-        self.board = Self::new_board(self.num_layers);
-        self.num_moves = 0;
-        self.num_moves_left = Self::count_num_moves_left(&self.board);
+        self.game = Game::new(self.num_layers, self.rules.clone());
+        self.thinking = None;
+        self.replay = None;
+        if self.network.take().is_some() {
+            self.message = Some("Left the network game".into());
+        }
+    }
+
+    fn is_human_turn(&self) -> bool {
+        match &self.network {
+            Some(network) => self.game.current_player() == network.side(),
+            None => self.players[side_index(self.game.current_player())].kind == PlayerKind::Human,
+        }
+    }
+
+    /// The game can only be changed by moves while playing over the network
+    fn check_not_networked(&mut self) -> bool {
+        if self.network.is_some() {
+            self.message = Some("Not available in a network game".into());
+        }
+        self.network.is_none()
+    }
+
+    fn host(&mut self) {
+        let listener = match std::net::TcpListener::bind(("0.0.0.0", self.network_port)) {
+            Ok(listener) => listener,
+            Err(error) => {
+                self.message = Some(format!("Failed to host: {error}"));
+                return;
+            }
+        };
+        let (sender, receiver) = mpsc::channel();
+        let (num_layers, rules) = (self.num_layers, self.rules.clone());
+        std::thread::spawn(move || {
+            _ = sender.send(NetworkSession::host(
+                &listener,
+                num_layers,
+                rules,
+                State::Circle,
+            ));
+        });
+        self.connecting = Some(Connecting {
+            receiver,
+            hosting: Some(([127, 0, 0, 1], self.network_port).into()),
+        });
+        self.message = Some(format!(
+            "Waiting for a player on port {}",
+            self.network_port
+        ));
+    }
+
+    fn join(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        let address = self.network_address.clone();
+        std::thread::spawn(move || {
+            _ = sender.send(NetworkSession::join(address.as_str()));
+        });
+        self.connecting = Some(Connecting {
+            receiver,
+            hosting: None,
+        });
+        self.message = Some(format!("Joining {}", self.network_address));
     }
 
-    fn count_num_moves_left(board: &Board) -> usize {
-        board
-            .elements
+    fn cancel_connecting(&mut self) {
+        if let Some(address) = self
+            .connecting
+            .take()
+            .and_then(|connecting| connecting.hosting)
+        {
+            _ = std::net::TcpStream::connect(address);
+        }
+        self.message = None;
+    }
+
+    fn update_network(&mut self) {
+        if let Some(connecting) = &self.connecting {
+            match connecting.receiver.try_recv() {
+                Ok(Ok((network, game))) => {
+                    self.connecting = None;
+                    self.num_layers = game.num_layers();
+                    self.rules = game.rules().clone();
+                    self.game = game;
+                    self.game_changed();
+                    self.bots = Default::default();
+                    self.message = Some(format!(
+                        "Playing as {} against {}",
+                        network.side(),
+                        network.peer()
+                    ));
+                    self.network = Some(network);
+                }
+                Ok(Err(error)) => {
+                    self.connecting = None;
+                    self.message = Some(format!("Failed to connect: {error}"));
+                }
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => self.connecting = None,
+            }
+        }
+
+        if let Some(network) = &mut self.network {
+            match network.poll(&mut self.game) {
+                Ok(Some(outcome)) => self.game_over = outcome != Outcome::Ongoing,
+                Ok(None) => {}
+                Err(error) => {
+                    self.message = Some(format!("Network game ended: {error}"));
+                    self.network = None;
+                }
+            }
+        }
+    }
+
+    fn update_bots(&mut self) {
+        if self.network.is_some() || self.replay.is_some() {
+            return;
+        }
+        if let Some(thinking) = &self.thinking {
+            match thinking.try_recv() {
+                Ok(Ok((bot, mv))) => {
+                    self.thinking = None;
+                    let side = side_index(self.game.current_player());
+                    match mv.map(|mv| self.game.play(mv)) {
+                        Some(Ok(outcome)) => self.game_over = outcome != Outcome::Ongoing,
+                        // a bot that can't or won't move is handed over to a human, instead of asking it again
+                        _ => {
+                            self.message = Some(format!(
+                                "{} did not play a legal move{}",
+                                bot.name(),
+                                bot.report()
+                                    .map(|report| format!(": {report}"))
+                                    .unwrap_or_default()
+                            ));
+                            self.players[side].kind = PlayerKind::Human;
+                            return;
+                        }
+                    }
+                    self.bots[side] = Some(bot);
+                }
+                Ok(Err(error)) => {
+                    self.thinking = None;
+                    let side = side_index(self.game.current_player());
+                    self.message = Some(format!("Failed to start the engine: {error}"));
+                    self.players[side].kind = PlayerKind::Human;
+                    return;
+                }
+                Err(mpsc::TryRecvError::Empty) => return,
+                // the bot panicked, it will be recreated
+                Err(mpsc::TryRecvError::Disconnected) => self.thinking = None,
+            }
+        }
+
+        if self.game_over || self.game.status() != Outcome::Ongoing {
+            return;
+        }
+        let side = side_index(self.game.current_player());
+        let bot = self.bots[side].take();
+        if bot.is_none() && self.players[side].kind == PlayerKind::Human {
+            return;
+        }
+
+        // starting an engine waits for it to answer, which shouldn't hold up the interface
+        let player = self.players[side].clone();
+        let game = self.game.clone();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut bot = match bot.map_or_else(|| player.make_bot(), |bot| Ok(Some(bot))) {
+                Ok(Some(bot)) => bot,
+                Ok(None) => return,
+                Err(error) => {
+                    _ = sender.send(Err(error));
+                    return;
+                }
+            };
+            let mv = bot.choose_move(&game);
+            _ = sender.send(Ok((bot, mv)));
+        });
+        self.thinking = Some(receiver);
+    }
+
+    fn save(&mut self) {
+        let save = self.game.to_save_string(true);
+        self.message = Some(match std::fs::write(&self.save_path, save) {
+            Ok(()) => format!("Saved to {}", self.save_path),
+            Err(error) => format!("Failed to save: {error}"),
+        });
+    }
+
+    fn load(&mut self) {
+        if !self.check_not_networked() {
+            return;
+        }
+        let game = std::fs::read_to_string(&self.save_path)
+            .map_err(|error| error.to_string())
+            .and_then(|save| Game::from_save_str(&save).map_err(|error| error.to_string()));
+        self.message = Some(match game {
+            Ok(game) => {
+                self.num_layers = game.num_layers();
+                self.rules = game.rules().clone();
+                self.game = game;
+                self.game_changed();
+                format!("Loaded {}", self.save_path)
+            }
+            Err(error) => format!("Failed to load: {error}"),
+        });
+    }
+
+    fn load_position(&mut self) {
+        if !self.check_not_networked() {
+            return;
+        }
+        self.message = Some(
+            match Game::from_notation(&self.position, self.rules.clone()) {
+                Ok(game) => {
+                    self.num_layers = game.num_layers();
+                    self.game = game;
+                    self.game_changed();
+                    "Loaded position".into()
+                }
+                Err(error) => format!("Failed to load position: {error}"),
+            },
+        );
+    }
+
+    fn undo(&mut self) {
+        if !self.check_not_networked() {
+            return;
+        }
+        if self.game.undo().is_none() {
+            return;
+        }
+        // take back the computer's moves as well, otherwise it would just play them again
+        let has_human = self
+            .players
             .iter()
-            .flatten()
-            .map(|element| match element {
-                Element::State(None) => 1,
-                Element::State(Some(_)) => 0,
-                Element::Board(board) => Self::count_num_moves_left(board),
-            })
-            .sum()
+            .any(|player| player.kind == PlayerKind::Human);
+        while has_human && !self.is_human_turn() && self.game.undo().is_some() {}
+        self.game_changed();
     }
 
-    fn new_board(num_layers: usize) -> Board {
-        assert!(num_layers > 0);
-        let mut board = Board::default();
-        if num_layers > 1 {
-            board.elements.iter_mut().flatten().for_each(|e| {
-                let board = Self::new_board(num_layers - 1);
-                *e = Element::Board(Box::new(board));
-            });
+    fn redo(&mut self) {
+        if !self.check_not_networked() {
+            return;
+        }
+        if self.game.redo().is_some() {
+            self.game_changed();
         }
-        board
     }
 }
 
@@ -109,11 +416,11 @@ impl eframe::App for App {
         ctx.request_repaint();
 
         egui::SidePanel::left("Settings").show(ctx, |ui| {
-            ui.label(format!("Current Turn: {}", self.turn));
-            ui.label(format!("Number of moves: {}", self.num_moves));
+            ui.label(format!("Current Turn: {}", self.game.current_player()));
+            ui.label(format!("Number of moves: {}", self.game.num_moves()));
             ui.label(format!(
                 "Number of possible moves left: {}",
-                self.num_moves_left
+                self.game.num_moves_left()
             ));
             ui.label(format!("Number of layers: {}", self.num_layers));
             ui.horizontal(|ui| {
@@ -126,31 +433,239 @@ impl eframe::App for App {
                     self.restart();
                 }
             });
-            if ui.button("Reset").clicked() {
-                self.restart();
+            ui.collapsing("Board Shapes", |ui| {
+                let mut shapes = self.rules.layer_shapes(self.num_layers);
+                let mut changed = false;
+                for (layer, shape) in shapes.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Layer {}:", layer + 1));
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut shape.size).clamp_range(2..=9))
+                            .changed();
+                        ui.label("In a row:");
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(&mut shape.win_length)
+                                    .clamp_range(2..=shape.size),
+                            )
+                            .changed();
+                        shape.win_length = shape.win_length.min(shape.size);
+                    });
+                }
+                if changed {
+                    self.rules.shapes = shapes;
+                    self.restart();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Rules:");
+                let placement = self.rules.placement;
+                egui::ComboBox::from_id_source("Placement")
+                    .selected_text(placement.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in [Placement::Free, Placement::SentTo] {
+                            ui.selectable_value(
+                                &mut self.rules.placement,
+                                option,
+                                option.to_string(),
+                            );
+                        }
+                    });
+                if self.rules.placement != placement {
+                    self.restart();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Draws:");
+                let draw_policy = self.rules.draw_policy;
+                egui::ComboBox::from_id_source("Draw policy")
+                    .selected_text(draw_policy.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in DrawPolicy::ALL {
+                            ui.selectable_value(
+                                &mut self.rules.draw_policy,
+                                option,
+                                option.to_string(),
+                            );
+                        }
+                    });
+                if self.rules.draw_policy != draw_policy {
+                    self.restart();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Variant:");
+                let misere = self.rules.misere;
+                egui::ComboBox::from_id_source("Misere")
+                    .selected_text(misere.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in Misere::ALL {
+                            ui.selectable_value(&mut self.rules.misere, option, option.to_string());
+                        }
+                    });
+                if self.rules.misere != misere {
+                    self.restart();
+                }
+            });
+            for state in [State::Circle, State::Cross] {
+                let side = side_index(state);
+                ui.horizontal(|ui| {
+                    ui.label(format!("{state}:"));
+                    if self.players[side].ui(ui, &format!("{state} Player")) {
+                        self.bots[side] = None;
+                        if self.game.current_player() == state {
+                            self.thinking = None;
+                        }
+                    }
+                });
+            }
+            if self.thinking.is_some() {
+                ui.label("Thinking...");
             }
-            ui.allocate_space(ui.available_size());
+            ui.collapsing("Network", |ui| {
+                if let Some(network) = &self.network {
+                    ui.label(format!(
+                        "Playing as {} against {}",
+                        network.side(),
+                        network.peer()
+                    ));
+                    if ui.button("Leave").clicked() {
+                        self.restart();
+                    }
+                } else if self.connecting.is_some() {
+                    if ui.button("Cancel").clicked() {
+                        self.cancel_connecting();
+                    }
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.label("Port:");
+                        ui.add(egui::DragValue::new(&mut self.network_port));
+                        if ui.button("Host as Circle").clicked() {
+                            self.host();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Address:");
+                        ui.text_edit_singleline(&mut self.network_address);
+                        if ui.button("Join").clicked() {
+                            self.join();
+                        }
+                    });
+                }
+            });
+            for (state, bot) in [State::Circle, State::Cross].into_iter().zip(&self.bots) {
+                if let Some(report) = bot.as_ref().and_then(|bot| bot.report()) {
+                    ui.collapsing(format!("{state} Report"), |ui| {
+                        ui.monospace(report);
+                    });
+                }
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Reset").clicked() {
+                    self.restart();
+                }
+                if ui.button("Save").clicked() {
+                    self.save();
+                }
+                if ui.button("Load").clicked() {
+                    self.load();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.text_edit_singleline(&mut self.save_path);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Position:");
+                ui.text_edit_singleline(&mut self.position);
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Copy Position").clicked() {
+                    self.position = self.game.to_notation();
+                    ui.output_mut(|o| o.copied_text = self.position.clone());
+                }
+                if ui.button("Load Position").clicked() {
+                    self.load_position();
+                }
+            });
+            if let Some(message) = &self.message {
+                ui.label(message);
+            }
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(self.game.can_undo(), egui::Button::new("Undo"))
+                    .clicked()
+                {
+                    self.undo();
+                }
+                if ui
+                    .add_enabled(self.game.can_redo(), egui::Button::new("Redo"))
+                    .clicked()
+                {
+                    self.redo();
+                }
+            });
+            ui.separator();
+            if let Some(replay) = &mut self.replay {
+                replay_ui(ui, replay);
+                if ui.button("Exit Replay").clicked() {
+                    self.replay = None;
+                    self.game_over = self.game.status() != Outcome::Ongoing;
+                }
+                ui.separator();
+            } else if ui
+                .add_enabled(self.game.can_undo(), egui::Button::new("Replay Game"))
+                .clicked()
+            {
+                self.replay = Some(Replay::new(&self.game));
+            }
+            ui.label("Moves:");
+            egui::ScrollArea::vertical()
+                .auto_shrink([false, false])
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    let mut turn = State::Circle;
+                    for (i, mv) in self.game.moves().enumerate() {
+                        ui.monospace(format!("{:>3}. {turn:<6} {}", i + 1, mv.path));
+                        turn = turn.opponent();
+                    }
+                });
         });
 
         let was_game_over = self.game_over;
+        let mut start_replay = false;
         if egui::Window::new("Game Over")
             .open(&mut self.game_over)
             .show(ctx, |ui| {
-                if let Some(winner) = self.board.get_winner() {
-                    ui.label(format!("{winner} won the game!"));
-                    false
-                } else if self.board.is_stalemate() {
-                    ui.label("A stalemate has occured, nobody wins");
-                    false
-                } else {
-                    true
-                }
+                let ongoing = match self.game.status() {
+                    Outcome::Won(winner) if self.game.rules().misere != Misere::Normal => {
+                        ui.label(format!(
+                            "{} completed a line and lost, {winner} won the game!",
+                            winner.opponent()
+                        ));
+                        false
+                    }
+                    Outcome::Won(winner) => {
+                        ui.label(format!("{winner} won the game!"));
+                        false
+                    }
+                    Outcome::Stalemate => {
+                        ui.label("A stalemate has occured, nobody wins");
+                        false
+                    }
+                    Outcome::Ongoing => true,
+                };
+                start_replay = ui.button("Replay").clicked();
+                ongoing
             })
             .and_then(|r| r.inner)
             .unwrap_or(false)
             || (was_game_over && !self.game_over)
         {
             self.restart();
+        } else if start_replay {
+            self.game_over = false;
+            self.replay = Some(Replay::new(&self.game));
         }
 
         let egui::InnerResponse {
@@ -164,11 +679,36 @@ impl eframe::App for App {
 
                 self.camera.screen_size = (size.x, size.y).into();
 
+                let game = self.replay.as_ref().map_or(&self.game, Replay::game);
                 let mut per_object_data = vec![];
+                let mut highlight = |path: &CellPath, color: (f32, f32, f32)| {
+                    let (position, size) = path.world_rect(game.board());
+                    per_object_data.push(PerObjectData {
+                        object_position: position,
+                        rotation: cgmath::Rad::from(cgmath::Deg(0.0)).0,
+                        scale: size,
+                        color: color.into(),
+                        alpha: 1.0,
+                        is_circle: 0,
+                        circle_width: 0.0,
+                    });
+                };
+                if !game.active_board().is_empty() {
+                    highlight(game.active_board(), (0.1, 0.3, 0.1));
+                }
+                if let Some(collapsed) = self
+                    .replay
+                    .as_ref()
+                    .and_then(|replay| replay.game().last_collapsed())
+                {
+                    highlight(collapsed, (0.4, 0.3, 0.05));
+                }
                 render_board(
-                    &self.board,
+                    game.board(),
                     (0.0, 0.0).into(),
-                    (1.0, 1.0).into(),
+                    cgmath::vec2(BOARD_SIZE, BOARD_SIZE) / game.board().size() as f32,
+                    game.moves().last().map(|mv| &mv.path[..]),
+                    game.rules().draw_policy,
                     &mut per_object_data,
                 );
 
@@ -194,7 +734,10 @@ impl eframe::App for App {
                 (rect, response)
             });
 
-        if response.clicked() && !self.game_over {
+        self.update_network();
+        self.update_bots();
+
+        if response.clicked() && !self.game_over && self.replay.is_none() && self.is_human_turn() {
             let click_pos = response.interact_pointer_pos().unwrap();
             if rect.contains(click_pos) {
                 let ndc_coords = ((click_pos - rect.left_top()) / rect.size() * 2.0
@@ -231,78 +774,22 @@ impl eframe::App for App {
                     / self.camera.scale
                     + self.camera.position;
 
-                fn get_colliding_state(
-                    board: &mut Board,
-                    cursor_position: cgmath::Vector2<f32>,
-                    position: cgmath::Vector2<f32>,
-                    scale: cgmath::Vector2<f32>,
-                ) -> Option<&mut Option<State>> {
-                    for (x, column) in board.elements.iter_mut().enumerate() {
-                        for (y, element) in column.iter_mut().enumerate() {
-                            let position: egui::Pos2 = Into::<(f32, f32)>::into(
-                                position
-                                    + cgmath::vec2(
-                                        (x as f32 - 1.0) * scale.x,
-                                        (y as f32 - 1.0) * scale.y,
-                                    ),
-                            )
-                            .into();
-                            let rect = egui::Rect {
-                                min: position - egui::vec2(scale.x * 0.5, scale.y * 0.5),
-                                max: position + egui::vec2(scale.x * 0.5, scale.y * 0.5),
-                            };
-
-                            if rect.contains((cursor_position.x, cursor_position.y).into()) {
-                                return match element {
-                                    Element::State(state) => Some(state),
-                                    Element::Board(board) => get_colliding_state(
-                                        board,
-                                        cursor_position,
-                                        (position.x, position.y).into(),
-                                        scale / 3.0,
-                                    ),
-                                };
+                if let Some(path) = CellPath::from_world_position(self.game.board(), position) {
+                    let played = match &mut self.network {
+                        Some(network) => match network.play(&mut self.game, Move { path }) {
+                            Err(NetworkError::Io(error)) => {
+                                self.message = Some(format!("Network game ended: {error}"));
+                                self.network = None;
+                                None
                             }
-                        }
-                    }
-                    None
-                }
-
-                if let Some(state @ None) = get_colliding_state(
-                    &mut self.board,
-                    position,
-                    (0.0, 0.0).into(),
-                    (1.0, 1.0).into(),
-                ) {
-                    *state = Some(self.turn);
-
-                    fn collapse_states(board: &mut Board) {
-                        for element in board.elements.iter_mut().flatten() {
-                            match element {
-                                Element::State(_) => {}
-                                Element::Board(board) => {
-                                    if let Some(winner) = board.get_winner() {
-                                        *element = Element::State(Some(winner));
-                                    } else {
-                                        collapse_states(board);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    collapse_states(&mut self.board);
-
-                    if self.board.get_winner().is_some() || self.board.is_stalemate() {
-                        self.game_over = true;
-                    }
-
-                    self.num_moves += 1;
-                    self.num_moves_left = Self::count_num_moves_left(&self.board);
-
-                    self.turn = match self.turn {
-                        State::Circle => State::Cross,
-                        State::Cross => State::Circle,
+                            result => result.ok(),
+                        },
+                        None => self.game.play(Move { path }).ok(),
                     };
+                    // illegal moves are just ignored
+                    if let Some(outcome) = played {
+                        self.game_over = outcome != Outcome::Ongoing;
+                    }
                 }
             }
         }
@@ -318,6 +805,19 @@ impl eframe::App for App {
         }
 
         if !ctx.wants_keyboard_input() {
+            let (undo, redo) = ctx.input(|i| {
+                (
+                    i.modifiers.command && i.key_pressed(egui::Key::Z),
+                    i.modifiers.command && i.key_pressed(egui::Key::Y),
+                )
+            });
+            if undo {
+                self.undo();
+            }
+            if redo {
+                self.redo();
+            }
+
             ctx.input(|i| {
                 const CAMERA_SPEED: f32 = 2.0;
                 if i.key_down(egui::Key::W) || i.key_down(egui::Key::ArrowUp) {
@@ -337,71 +837,252 @@ impl eframe::App for App {
     }
 }
 
-fn render_board(
+fn replay_ui(ui: &mut egui::Ui, replay: &mut Replay) {
+    ui.label("Replay:");
+    ui.horizontal(|ui| {
+        if ui.button("|<").clicked() {
+            replay.seek(0);
+        }
+        if ui.button("<").clicked() {
+            replay.step_back();
+        }
+        if ui.button(">").clicked() {
+            replay.step_forward();
+        }
+        if ui.button(">|").clicked() {
+            replay.seek(replay.len());
+        }
+    });
+    let mut position = replay.position();
+    if ui
+        .add(egui::Slider::new(&mut position, 0..=replay.len()).text("Move"))
+        .changed()
+    {
+        replay.seek(position);
+    }
+    match replay.last_move() {
+        Some(mv) => ui.label(format!(
+            "{} played {}",
+            replay.game().current_player().opponent(),
+            mv.path
+        )),
+        None => ui.label("Start of the game"),
+    };
+    if let Some(collapsed) = replay.game().last_collapsed() {
+        ui.label(format!("Board {collapsed} was won and collapsed"));
+    }
+}
+
+/// `scale` is the size of a single cell of the board, `last_move` is the path of the last played cell inside of
+/// this board, which gets highlighted. Winning lines are found the way `draw_policy` scores drawn sub-boards
+pub fn render_board(
     board: &Board,
     position: cgmath::Vector2<f32>,
     scale: cgmath::Vector2<f32>,
+    last_move: Option<&[(usize, usize)]>,
+    draw_policy: DrawPolicy,
     per_object_data: &mut Vec<PerObjectData>,
 ) {
+    let size = board.size() as f32;
+    for x in 0..=board.size() {
+        per_object_data.push(PerObjectData {
+            object_position: position + cgmath::vec2((x as f32 - size / 2.0) * scale.x, 0.0),
+            rotation: cgmath::Rad::from(cgmath::Deg(0.0)).0,
+            scale: cgmath::vec2(0.05 * scale.x, (size + 0.05) * scale.y),
+            color: (0.2, 0.2, 0.2).into(),
+            alpha: 1.0,
+            is_circle: 0,
+            circle_width: 0.0,
+        });
+    }
+    for y in 0..=board.size() {
+        per_object_data.push(PerObjectData {
+            object_position: position + cgmath::vec2(0.0, (y as f32 - size / 2.0) * scale.y),
+            rotation: cgmath::Rad::from(cgmath::Deg(0.0)).0,
+            scale: cgmath::vec2((size + 0.05) * scale.x, 0.05 * scale.y),
+            color: (0.2, 0.2, 0.2).into(),
+            alpha: 1.0,
+            is_circle: 0,
+            circle_width: 0.0,
+        });
+    }
+
     for (x, column) in board.elements.iter().enumerate() {
         for (y, element) in column.iter().enumerate() {
-            for x in 0..=3 {
-                per_object_data.push(PerObjectData {
-                    object_position: position + cgmath::vec2((x as f32 - 1.5) * scale.x, 0.0),
-                    rotation: cgmath::Rad::from(cgmath::Deg(0.0)).0,
-                    scale: cgmath::vec2(0.05 * scale.x, 3.05 * scale.y),
-                    color: (0.2, 0.2, 0.2).into(),
-                    is_circle: 0,
-                    circle_width: 0.0,
-                });
-            }
-            for y in 0..=3 {
+            let position = position + cell_offset(board.size(), (x, y), scale);
+            let last_move = last_move
+                .and_then(|path| path.split_first())
+                .filter(|&(&cell, _)| cell == (x, y))
+                .map(|(_, rest)| rest);
+            // a move inside of a collapsed board highlights the whole board
+            if last_move.is_some() && !matches!(element, Element::Board(_)) {
                 per_object_data.push(PerObjectData {
-                    object_position: position + cgmath::vec2(0.0, (y as f32 - 1.5) * scale.y),
+                    object_position: position,
                     rotation: cgmath::Rad::from(cgmath::Deg(0.0)).0,
-                    scale: cgmath::vec2(3.05 * scale.x, 0.05 * scale.y),
-                    color: (0.2, 0.2, 0.2).into(),
+                    scale,
+                    color: (1.0, 0.9, 0.2).into(),
+                    alpha: 0.35,
                     is_circle: 0,
                     circle_width: 0.0,
                 });
             }
-
-            let position =
-                position + cgmath::vec2((x as f32 - 1.0) * scale.x, (y as f32 - 1.0) * scale.y);
             match element {
                 Element::State(None) => {} // nothing to render
-                Element::State(Some(State::Circle)) => {
-                    per_object_data.push(PerObjectData {
-                        object_position: position,
-                        rotation: cgmath::Rad::from(cgmath::Deg(0.0)).0,
-                        scale,
-                        color: (0.0, 0.0, 1.0).into(),
-                        is_circle: 1,
-                        circle_width: 0.1,
-                    });
+                &Element::State(Some(state)) => {
+                    render_state(state, position, scale, per_object_data)
                 }
-                Element::State(Some(State::Cross)) => {
-                    per_object_data.push(PerObjectData {
-                        object_position: position,
-                        rotation: cgmath::Rad::from(cgmath::Deg(45.0)).0,
-                        scale: cgmath::vec2(0.1 * scale.x, scale.y),
-                        color: (1.0, 0.0, 0.0).into(),
-                        is_circle: 0,
-                        circle_width: 0.0,
-                    });
-                    per_object_data.push(PerObjectData {
-                        object_position: position,
-                        rotation: cgmath::Rad::from(cgmath::Deg(-45.0)).0,
-                        scale: cgmath::vec2(0.1 * scale.x, scale.y),
-                        color: (1.0, 0.0, 0.0).into(),
-                        is_circle: 0,
-                        circle_width: 0.0,
-                    });
+                &Element::Won { winner, ref board } => {
+                    // the contents of won boards are drawn faded out, under the winner
+                    let start = per_object_data.len();
+                    let inner_scale = scale / board.size() as f32;
+                    render_board(
+                        board,
+                        position,
+                        inner_scale,
+                        None,
+                        draw_policy,
+                        per_object_data,
+                    );
+                    for object in &mut per_object_data[start..] {
+                        object.alpha *= 0.25;
+                    }
+                    render_state(winner, position, scale, per_object_data);
                 }
                 Element::Board(board) => {
-                    render_board(board, position, scale / 3.0, per_object_data)
+                    let inner_scale = scale / board.size() as f32;
+                    render_board(
+                        board,
+                        position,
+                        inner_scale,
+                        last_move,
+                        draw_policy,
+                        per_object_data,
+                    )
                 }
             }
         }
     }
+
+    if let Some((line_owner, line)) = board.winning_line_with(draw_policy) {
+        let cell_position = |cell| position + cell_offset(board.size(), cell, scale);
+        let (start, end) = (cell_position(line[0]), cell_position(line[line.len() - 1]));
+        let direction = end - start;
+        let color = match line_owner {
+            State::Circle => (0.4, 0.6, 1.0),
+            State::Cross => (1.0, 0.5, 0.4),
+        };
+        per_object_data.push(PerObjectData {
+            object_position: (start + end) / 2.0,
+            rotation: direction.x.atan2(direction.y),
+            scale: cgmath::vec2(
+                0.15 * scale.x,
+                (direction.x * direction.x + direction.y * direction.y).sqrt() + 0.8 * scale.y,
+            ),
+            color: color.into(),
+            alpha: 0.8,
+            is_circle: 0,
+            circle_width: 0.0,
+        });
+    }
+}
+
+fn render_state(
+    state: State,
+    position: cgmath::Vector2<f32>,
+    scale: cgmath::Vector2<f32>,
+    per_object_data: &mut Vec<PerObjectData>,
+) {
+    match state {
+        State::Circle => {
+            per_object_data.push(PerObjectData {
+                object_position: position,
+                rotation: cgmath::Rad::from(cgmath::Deg(0.0)).0,
+                scale,
+                color: (0.0, 0.0, 1.0).into(),
+                alpha: 1.0,
+                is_circle: 1,
+                circle_width: 0.1,
+            });
+        }
+        State::Cross => {
+            per_object_data.push(PerObjectData {
+                object_position: position,
+                rotation: cgmath::Rad::from(cgmath::Deg(45.0)).0,
+                scale: cgmath::vec2(0.1 * scale.x, scale.y),
+                color: (1.0, 0.0, 0.0).into(),
+                alpha: 1.0,
+                is_circle: 0,
+                circle_width: 0.0,
+            });
+            per_object_data.push(PerObjectData {
+                object_position: position,
+                rotation: cgmath::Rad::from(cgmath::Deg(-45.0)).0,
+                scale: cgmath::vec2(0.1 * scale.x, scale.y),
+                color: (1.0, 0.0, 0.0).into(),
+                alpha: 1.0,
+                is_circle: 0,
+                circle_width: 0.0,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(
+        board: &Board,
+        last_move: Option<&[(usize, usize)]>,
+        draw_policy: DrawPolicy,
+    ) -> Vec<PerObjectData> {
+        let mut per_object_data = vec![];
+        let scale = cgmath::vec2(BOARD_SIZE, BOARD_SIZE) / board.size() as f32;
+        render_board(
+            board,
+            (0.0, 0.0).into(),
+            scale,
+            last_move,
+            draw_policy,
+            &mut per_object_data,
+        );
+        per_object_data
+    }
+
+    fn count_instances(board: &Board, last_move: Option<&[(usize, usize)]>) -> usize {
+        render(board, last_move, DrawPolicy::default()).len()
+    }
+
+    #[test]
+    fn grid_is_rendered_once_per_board() {
+        // 4 vertical and 4 horizontal bars for every board on every layer
+        let mut num_boards = 0;
+        for num_layers in 1..=4 {
+            num_boards += 9usize.pow(num_layers as u32 - 1);
+            assert_eq!(
+                count_instances(&Board::new(num_layers), None),
+                8 * num_boards
+            );
+        }
+        let shapes = ["4:3".parse().unwrap(), "2:2".parse().unwrap()];
+        assert_eq!(
+            count_instances(&Board::with_shapes(&shapes), None),
+            10 + 16 * 6
+        );
+
+        // a circle and a highlight for the last move
+        let board: Board = "O8".parse().unwrap();
+        assert_eq!(count_instances(&board, Some(&[(0, 2)])), 8 + 2);
+    }
+
+    #[test]
+    fn winning_lines_follow_the_draw_policy() {
+        // the drawn board only completes the line of circles when it counts for both players
+        let board: Board = "[OXOOXXXOO]OO6".parse().unwrap();
+        let dead = render(&board, None, DrawPolicy::Dead);
+        let counts_for_both = render(&board, None, DrawPolicy::CountsForBoth);
+        assert_eq!(counts_for_both.len(), dead.len() + 1);
+        let color = counts_for_both.last().unwrap().color;
+        assert_eq!(color, (0.4, 0.6, 1.0).into());
+    }
 }
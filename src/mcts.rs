@@ -0,0 +1,271 @@
+use std::time::{Duration, Instant};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{BitBoard, Bot, Game, Move, Outcome, State};
+
+/// Monte Carlo tree search using UCT with random playouts
+///
+/// The search stops after `iterations` or when `time_limit` runs out, whichever comes first.
+/// The random number generator is seeded from `seed` and the number of moves played,
+/// so a search that is only limited by iterations always picks the same move for the same position
+#[derive(Debug, Clone)]
+pub struct MctsBot {
+    pub iterations: usize,
+    pub time_limit: Duration,
+    pub seed: u64,
+    pub exploration: f32,
+    last_report: Option<SearchReport>,
+}
+
+impl Default for MctsBot {
+    fn default() -> Self {
+        Self {
+            iterations: 10_000,
+            time_limit: Duration::from_secs(2),
+            seed: 0,
+            exploration: std::f32::consts::SQRT_2,
+            last_report: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MoveStats {
+    pub mv: Move,
+    pub visits: u32,
+    /// How often playouts through this move were won by the player making it, draws count as half a win
+    pub win_rate: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchReport {
+    pub iterations: usize,
+    /// The candidate moves, most visited first
+    pub moves: Vec<MoveStats>,
+}
+
+impl std::fmt::Display for SearchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} iterations", self.iterations)?;
+        for stats in &self.moves {
+            write!(
+                f,
+                "\n{:<12} {:>7} visits {:>5.1}%",
+                stats.mv.path.to_string(),
+                stats.visits,
+                stats.win_rate * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Node {
+    mv: Option<Move>,
+    /// The player that made the move leading to this node
+    player: State,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// The legal moves are expanded in the order of [`Game::nth_legal_move`], this is the next one to try
+    untried: usize,
+    num_moves: usize,
+    visits: u32,
+    wins: f32,
+}
+
+/// Plays `mv` on `game`, and on the bit board following it if there is one
+fn play(game: &mut Game, bitboard: &mut Option<BitBoard>, mv: Move) {
+    if let Some(bitboard) = bitboard {
+        bitboard.set_cell(bitboard.cell_index(&mv.path), Some(game.current_player()));
+    }
+    game.play(mv).unwrap();
+}
+
+impl MctsBot {
+    pub fn last_report(&self) -> Option<&SearchReport> {
+        self.last_report.as_ref()
+    }
+
+    pub fn search(&mut self, game: &Game) -> SearchReport {
+        let deadline = Instant::now() + self.time_limit;
+        let mut rng = StdRng::seed_from_u64(self.seed ^ game.num_moves() as u64);
+
+        let mut nodes = vec![Node {
+            mv: None,
+            player: game.current_player().opponent(),
+            parent: None,
+            children: vec![],
+            untried: 0,
+            num_moves: game.num_legal_moves(),
+            visits: 0,
+            wins: 0.0,
+        }];
+
+        // every iteration plays its moves on the same copy and takes them back afterwards
+        let mut game = game.clone();
+        let root_moves = game.num_moves();
+        // playouts are much cheaper on a bit board, which follows the game when its rules allow it
+        let mut bitboard = BitBoard::from_game(&game).ok();
+        let mut iterations = 0;
+        while iterations < self.iterations.max(1) && Instant::now() < deadline {
+            iterations += 1;
+
+            // selection
+            let mut node = 0;
+            while nodes[node].untried == nodes[node].num_moves && !nodes[node].children.is_empty() {
+                let parent_visits = (nodes[node].visits as f32).ln();
+                node = *nodes[node]
+                    .children
+                    .iter()
+                    .max_by(|&&a, &&b| {
+                        let uct = |node: &Node| {
+                            node.wins / node.visits as f32
+                                + self.exploration * (parent_visits / node.visits as f32).sqrt()
+                        };
+                        uct(&nodes[a]).total_cmp(&uct(&nodes[b]))
+                    })
+                    .unwrap();
+                play(&mut game, &mut bitboard, nodes[node].mv.clone().unwrap());
+            }
+
+            // expansion
+            if nodes[node].untried < nodes[node].num_moves {
+                let mv = game.nth_legal_move(nodes[node].untried).unwrap();
+                nodes[node].untried += 1;
+                let player = game.current_player();
+                play(&mut game, &mut bitboard, mv.clone());
+                nodes.push(Node {
+                    mv: Some(mv),
+                    player,
+                    parent: Some(node),
+                    children: vec![],
+                    untried: 0,
+                    num_moves: game.num_legal_moves(),
+                    visits: 0,
+                    wins: 0.0,
+                });
+                let child = nodes.len() - 1;
+                nodes[node].children.push(child);
+                node = child;
+            }
+
+            // simulation
+            let outcome = match &mut bitboard {
+                Some(bitboard) => bitboard.random_playout(
+                    game.rules().placement,
+                    game.active_board(),
+                    game.current_player(),
+                    &mut rng,
+                ),
+                None => loop {
+                    match game.status() {
+                        Outcome::Ongoing => {}
+                        outcome => break outcome,
+                    }
+                    let mv = game.random_move(&mut rng).unwrap();
+                    game.play(mv).unwrap();
+                },
+            };
+            while game.num_moves() > root_moves {
+                let mv = game.take_back().unwrap();
+                if let Some(bitboard) = &mut bitboard {
+                    bitboard.set_cell(bitboard.cell_index(&mv.path), None);
+                }
+            }
+
+            // backpropagation
+            let mut current = Some(node);
+            while let Some(node) = current {
+                let node = &mut nodes[node];
+                node.visits += 1;
+                node.wins += match outcome {
+                    Outcome::Won(winner) if winner == node.player => 1.0,
+                    Outcome::Won(_) => 0.0,
+                    Outcome::Stalemate | Outcome::Ongoing => 0.5,
+                };
+                current = node.parent;
+            }
+        }
+
+        let mut moves = nodes[0]
+            .children
+            .iter()
+            .map(|&child| {
+                let node = &nodes[child];
+                MoveStats {
+                    mv: node.mv.clone().unwrap(),
+                    visits: node.visits,
+                    win_rate: node.wins / node.visits.max(1) as f32,
+                }
+            })
+            .collect::<Vec<_>>();
+        moves.sort_by_key(|stats| std::cmp::Reverse(stats.visits));
+
+        let report = SearchReport { iterations, moves };
+        self.last_report = Some(report.clone());
+        report
+    }
+}
+
+impl Bot for MctsBot {
+    fn name(&self) -> String {
+        format!("MCTS ({} iterations)", self.iterations)
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Move> {
+        let report = self.search(game);
+        report.moves.into_iter().next().map(|stats| stats.mv)
+    }
+
+    fn set_time_limit(&mut self, time_limit: Duration) {
+        self.time_limit = time_limit;
+    }
+
+    fn report(&self) -> Option<String> {
+        self.last_report.as_ref().map(|report| report.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DrawPolicy, Rules};
+
+    fn bot(iterations: usize) -> MctsBot {
+        MctsBot {
+            iterations,
+            time_limit: Duration::from_secs(60),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_the_win() {
+        // drawn boards counting for both players keeps the playouts off of the bit board
+        let draws = Rules {
+            draw_policy: DrawPolicy::CountsForBoth,
+            ..Default::default()
+        };
+        for rules in [Rules::default(), draws] {
+            let game = Game::from_notation("OO1XX4 O", rules).unwrap();
+            let report = bot(2000).search(&game);
+            assert_eq!(report.iterations, 2000);
+            assert_eq!(report.moves[0].mv.path.to_string(), "2");
+            assert_eq!(report.moves[0].win_rate, 1.0);
+            assert_eq!(
+                report.moves.iter().map(|stats| stats.visits).sum::<u32>(),
+                2000
+            );
+        }
+    }
+
+    #[test]
+    fn seeded_search_is_deterministic() {
+        let game = Game::new(2, Rules::default());
+        let first = bot(300).choose_move(&game);
+        let second = bot(300).choose_move(&game);
+        assert!(first.is_some());
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,222 @@
+use std::time::{Duration, Instant};
+
+use crate::{Board, Bot, Element, Game, Misere, Move, Outcome, State};
+
+const WIN_SCORE: f32 = 1000.0;
+
+/// Scores a board from circle's point of view, between -1 (cross has won) and 1 (circle has won)
+///
+/// Every line of a board is scored by how likely each player is to complete it, using the scores of
+/// its elements, so control of sub-boards counts at every layer
+pub fn evaluate(board: &Board) -> f32 {
+    if let Some(winner) = board.get_winner() {
+        return match winner {
+            State::Circle => 1.0,
+            State::Cross => -1.0,
+        };
+    }
+    if board.is_stalemate() {
+        return 0.0;
+    }
+
+    let values: Vec<Vec<f32>> = board
+        .elements
+        .iter()
+        .map(|column| column.iter().map(element_value).collect())
+        .collect();
+
+    let score: f32 = board
+        .shape()
+        .lines()
+        .map(|line| {
+            let circle: f32 = line
+                .clone()
+                .map(|(x, y)| (1.0 + values[x][y]) * 0.5)
+                .product();
+            let cross: f32 = line.map(|(x, y)| (1.0 - values[x][y]) * 0.5).product();
+            circle - cross
+        })
+        .sum();
+
+    // an unfinished board should never score as much as a won one
+    score.tanh() * 0.9
+}
+
+fn element_value(element: &Element) -> f32 {
+    match element {
+        Element::State(None) => 0.0,
+        Element::State(Some(State::Circle))
+        | Element::Won {
+            winner: State::Circle,
+            ..
+        } => 1.0,
+        Element::State(Some(State::Cross))
+        | Element::Won {
+            winner: State::Cross,
+            ..
+        } => -1.0,
+        Element::Board(board) => evaluate(board),
+    }
+}
+
+/// Alpha-beta search over the game tree, with iterative deepening so it can stop when it runs out of time
+#[derive(Debug, Clone, Copy)]
+pub struct MinimaxBot {
+    pub max_depth: usize,
+    pub time_limit: Duration,
+}
+
+impl Default for MinimaxBot {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            time_limit: Duration::from_secs(1),
+        }
+    }
+}
+
+impl MinimaxBot {
+    /// Returns the best move for the current player and its score from their point of view
+    pub fn search(&self, game: &Game) -> Option<(Move, f32)> {
+        let deadline = Instant::now() + self.time_limit;
+        let mut game = game.clone();
+        let mut moves = game.legal_moves();
+        let mut best = None;
+
+        for depth in 1..=self.max_depth.max(1) {
+            let mut alpha = -f32::INFINITY;
+            let mut depth_best = None;
+            for (i, mv) in moves.iter().enumerate() {
+                game.play_move(mv.clone()).unwrap();
+                let score = negamax(&mut game, depth - 1, 1, -f32::INFINITY, -alpha, deadline);
+                game.take_back();
+                // ran out of time, so this depth can't be trusted
+                let Some(score) = score.map(|score| -score) else {
+                    return best.or_else(|| moves.first().map(|mv| (mv.clone(), 0.0)));
+                };
+                if score > alpha {
+                    alpha = score;
+                    depth_best = Some(i);
+                }
+            }
+
+            let Some(i) = depth_best else {
+                break;
+            };
+            // search the best move first at the next depth
+            let mv = moves.remove(i);
+            best = Some((mv.clone(), alpha));
+            moves.insert(0, mv);
+
+            if alpha.abs() >= WIN_SCORE - self.max_depth as f32 {
+                break;
+            }
+        }
+        best
+    }
+}
+
+fn negamax(
+    game: &mut Game,
+    depth: usize,
+    ply: usize,
+    mut alpha: f32,
+    beta: f32,
+    deadline: Instant,
+) -> Option<f32> {
+    if Instant::now() >= deadline {
+        return None;
+    }
+
+    let sign = match game.current_player() {
+        State::Circle => 1.0,
+        State::Cross => -1.0,
+    };
+    match game.status() {
+        Outcome::Won(winner) if winner == game.current_player() => {
+            return Some(WIN_SCORE - ply as f32)
+        }
+        Outcome::Won(_) => return Some(-(WIN_SCORE - ply as f32)),
+        Outcome::Stalemate => return Some(0.0),
+        Outcome::Ongoing => {}
+    }
+    if depth == 0 {
+        // lines are bad for whoever owns them in misère, so the evaluation is turned around
+        let misere_sign = match game.rules().misere {
+            Misere::Normal => 1.0,
+            Misere::TopLevel | Misere::AllLayers => -1.0,
+        };
+        return Some(evaluate(game.board()) * sign * misere_sign);
+    }
+
+    let mut best = -f32::INFINITY;
+    for mv in game.legal_moves() {
+        game.play_move(mv).unwrap();
+        let score = negamax(game, depth - 1, ply + 1, -beta, -alpha, deadline);
+        game.take_back();
+        let score = -score?;
+        best = best.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    Some(best)
+}
+
+impl Bot for MinimaxBot {
+    fn name(&self) -> String {
+        format!("Minimax (depth {})", self.max_depth)
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Move> {
+        self.search(game).map(|(mv, _)| mv)
+    }
+
+    fn set_time_limit(&mut self, time_limit: Duration) {
+        self.time_limit = time_limit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rules;
+
+    #[test]
+    fn evaluation_is_symmetric() {
+        assert_eq!(evaluate(&Board::new(2)), 0.0);
+        assert_eq!(evaluate(&"OOO6".parse().unwrap()), 1.0);
+        let circle = evaluate(&"[O8]8".parse().unwrap());
+        let cross = evaluate(&"[X8]8".parse().unwrap());
+        assert!(circle > 0.0 && circle < 1.0);
+        assert!((circle + cross).abs() < 1e-6);
+    }
+
+    #[test]
+    fn takes_the_win() {
+        let game = Game::from_notation("OO1XX4 O", Rules::default()).unwrap();
+        let (mv, score) = MinimaxBot::default().search(&game).unwrap();
+        assert_eq!(mv.path.to_string(), "2");
+        assert!(score >= WIN_SCORE - 1.0);
+    }
+
+    #[test]
+    fn blocks_the_loss() {
+        let game = Game::from_notation("XX1O5 O", Rules::default()).unwrap();
+        let mv = MinimaxBot::default().choose_move(&game).unwrap();
+        assert_eq!(mv.path.to_string(), "2");
+    }
+
+    #[test]
+    fn search_keeps_redo_history() {
+        let mut game = Game::new(2, Rules::default());
+        let mv = game.legal_moves()[0].clone();
+        game.play(mv.clone()).unwrap();
+        game.undo();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        negamax(&mut game, 2, 0, -f32::INFINITY, f32::INFINITY, deadline).unwrap();
+        assert_eq!(game.num_moves(), 0);
+        assert_eq!(game.redo(), Some(mv));
+    }
+}
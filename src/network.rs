@@ -0,0 +1,397 @@
+//! Playing a game between two instances over TCP
+//!
+//! The protocol is line based. When a player joins, the host sends
+//! `hello layers=<n> side=<circle|cross> placement=<free|sent-to> [draws=<dead|both|majority>]
+//! [misere=<off|top|all>] [boards=<size>:<in a row>,...]` with the side of the joining player,
+//! after that both send `move <cell>` for their own moves, `error <text>` when the other side did something wrong
+//! and `quit` when leaving. Both sides check every move against their own copy of the game,
+//! and a game with more layers or cells than [`Rules::within_limits`] allows isn't joined. Lines longer than
+//! [`MAX_LINE_LENGTH`] end the connection
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::mpsc,
+};
+
+use crate::{CellPath, Game, Move, MoveError, Outcome, Rules, State};
+
+/// The longest line read from the other end of a connection, longer lines end the connection
+pub const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// Reads the next line like [`BufRead::lines`] does, without reading more than [`MAX_LINE_LENGTH`] of it
+fn read_limited_line(reader: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let mut line = vec![];
+    let limit = MAX_LINE_LENGTH as u64 + 1;
+    if reader.by_ref().take(limit).read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    } else if line.len() > MAX_LINE_LENGTH {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("a line is longer than {MAX_LINE_LENGTH} bytes"),
+        ));
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+}
+
+/// The lines of `reader` like [`BufRead::lines`], with an error instead of a line that is longer than
+/// [`MAX_LINE_LENGTH`], so a peer can't fill up the memory by never ending a line
+pub fn limited_lines(mut reader: impl BufRead) -> impl Iterator<Item = std::io::Result<String>> {
+    std::iter::from_fn(move || read_limited_line(&mut reader).transpose())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetMessage {
+    Hello {
+        num_layers: usize,
+        rules: Rules,
+        side: State,
+    },
+    Move(Move),
+    Error(String),
+    Quit,
+}
+
+impl std::fmt::Display for NetMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetMessage::Hello {
+                num_layers,
+                rules,
+                side,
+            } => {
+                write!(f, "hello layers={num_layers} side={}", side.name())?;
+                for (name, value) in rules.options() {
+                    write!(f, " {name}={value}")?;
+                }
+                Ok(())
+            }
+            NetMessage::Move(mv) => write!(f, "move {}", mv.path),
+            NetMessage::Error(text) => write!(f, "error {text}"),
+            NetMessage::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+impl std::str::FromStr for NetMessage {
+    type Err = NetworkError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid = || NetworkError::Protocol(line.to_string());
+        let line = line.trim();
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        Ok(match keyword {
+            "hello" => {
+                let (mut num_layers, mut rules, mut side) = (None, Rules::default(), None);
+                for option in rest.split_whitespace() {
+                    match option.split_once('=').ok_or_else(invalid)? {
+                        ("layers", layers) => num_layers = layers.parse().ok(),
+                        ("side", name) => side = Some(State::from_name(name).ok_or_else(invalid)?),
+                        (name, value) => {
+                            if !rules.set_option(name, value).map_err(|_| invalid())? {
+                                return Err(invalid());
+                            }
+                        }
+                    }
+                }
+                NetMessage::Hello {
+                    num_layers: num_layers
+                        .filter(|&layers| rules.within_limits(layers))
+                        .ok_or_else(invalid)?,
+                    rules,
+                    side: side.ok_or_else(invalid)?,
+                }
+            }
+            "move" => NetMessage::Move(Move {
+                path: rest.trim().parse::<CellPath>().map_err(|_| invalid())?,
+            }),
+            "error" => NetMessage::Error(rest.to_string()),
+            "quit" => NetMessage::Quit,
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Io(std::io::Error),
+    /// The other player sent something that isn't part of the protocol
+    Protocol(String),
+    NotYourTurn,
+    IllegalMove(MoveError),
+    /// The other player moved when it wasn't their turn
+    OpponentOutOfTurn,
+    OpponentIllegalMove(MoveError),
+    /// The other player reported an error
+    Opponent(String),
+    Disconnected,
+}
+
+impl std::fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::Io(error) => write!(f, "{error}"),
+            NetworkError::Protocol(line) => write!(f, "invalid message '{line}'"),
+            NetworkError::NotYourTurn => write!(f, "it is not your turn"),
+            NetworkError::IllegalMove(error) => write!(f, "{error}"),
+            NetworkError::OpponentOutOfTurn => write!(f, "the opponent moved out of turn"),
+            NetworkError::OpponentIllegalMove(error) => {
+                write!(f, "the opponent played an illegal move: {error}")
+            }
+            NetworkError::Opponent(error) => write!(f, "the opponent reported an error: {error}"),
+            NetworkError::Disconnected => write!(f, "the opponent left the game"),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+impl From<std::io::Error> for NetworkError {
+    fn from(error: std::io::Error) -> Self {
+        NetworkError::Io(error)
+    }
+}
+
+/// A connection to the other player of a network game
+///
+/// Incoming messages are read on a background thread, so [`NetworkSession::poll`] never blocks
+pub struct NetworkSession {
+    side: State,
+    stream: TcpStream,
+    peer: SocketAddr,
+    incoming: mpsc::Receiver<Result<NetMessage, NetworkError>>,
+}
+
+impl NetworkSession {
+    /// Waits for a player to connect to `listener` and starts a new game with them, the host plays `side`
+    pub fn host(
+        listener: &TcpListener,
+        num_layers: usize,
+        rules: Rules,
+        side: State,
+    ) -> Result<(Self, Game), NetworkError> {
+        let (mut stream, peer) = listener.accept()?;
+        let hello = NetMessage::Hello {
+            num_layers,
+            rules: rules.clone(),
+            side: side.opponent(),
+        };
+        writeln!(stream, "{hello}")?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok((
+            Self::new(side, stream, peer, reader),
+            Game::new(num_layers, rules),
+        ))
+    }
+
+    /// Joins the game hosted at `address`
+    pub fn join(address: impl ToSocketAddrs) -> Result<(Self, Game), NetworkError> {
+        let stream = TcpStream::connect(address)?;
+        let peer = stream.peer_addr()?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let Some(line) = read_limited_line(&mut reader)? else {
+            return Err(NetworkError::Disconnected);
+        };
+        match line.parse()? {
+            NetMessage::Hello {
+                num_layers,
+                rules,
+                side,
+            } => Ok((
+                Self::new(side, stream, peer, reader),
+                Game::new(num_layers, rules),
+            )),
+            _ => Err(NetworkError::Protocol(line.trim().to_string())),
+        }
+    }
+
+    fn new(side: State, stream: TcpStream, peer: SocketAddr, reader: BufReader<TcpStream>) -> Self {
+        let (sender, incoming) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in limited_lines(reader) {
+                let message = line
+                    .map_err(NetworkError::from)
+                    .and_then(|line| line.parse());
+                let stop = message.is_err();
+                if sender.send(message).is_err() || stop {
+                    break;
+                }
+            }
+        });
+        Self {
+            side,
+            stream,
+            peer,
+            incoming,
+        }
+    }
+
+    /// The side played on this end of the connection
+    pub fn side(&self) -> State {
+        self.side
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    fn send(&mut self, message: &NetMessage) -> std::io::Result<()> {
+        writeln!(self.stream, "{message}")
+    }
+
+    /// Plays a move for the local player and sends it to the other player
+    pub fn play(&mut self, game: &mut Game, mv: Move) -> Result<Outcome, NetworkError> {
+        if game.current_player() != self.side {
+            return Err(NetworkError::NotYourTurn);
+        }
+        game.check_move(&mv).map_err(NetworkError::IllegalMove)?;
+        self.send(&NetMessage::Move(mv.clone()))?;
+        Ok(game.play(mv).unwrap())
+    }
+
+    /// Applies the moves the other player has made since the last call without blocking,
+    /// returns the outcome after the last of them
+    pub fn poll(&mut self, game: &mut Game) -> Result<Option<Outcome>, NetworkError> {
+        let mut outcome = None;
+        loop {
+            match self.incoming.try_recv() {
+                Ok(message) => outcome = Some(self.handle(game, message)?),
+                Err(mpsc::TryRecvError::Empty) => return Ok(outcome),
+                Err(mpsc::TryRecvError::Disconnected) => return Err(NetworkError::Disconnected),
+            }
+        }
+    }
+
+    /// Blocks until the other player has made a move
+    pub fn wait_for_move(&mut self, game: &mut Game) -> Result<Outcome, NetworkError> {
+        let message = self
+            .incoming
+            .recv()
+            .map_err(|_| NetworkError::Disconnected)?;
+        self.handle(game, message)
+    }
+
+    fn handle(
+        &mut self,
+        game: &mut Game,
+        message: Result<NetMessage, NetworkError>,
+    ) -> Result<Outcome, NetworkError> {
+        let result = match message? {
+            NetMessage::Move(_) if game.current_player() == self.side => {
+                Err(NetworkError::OpponentOutOfTurn)
+            }
+            NetMessage::Move(mv) => game.play(mv).map_err(NetworkError::OpponentIllegalMove),
+            NetMessage::Hello { .. } => Err(NetworkError::Protocol("hello".into())),
+            NetMessage::Error(error) => return Err(NetworkError::Opponent(error)),
+            NetMessage::Quit => return Err(NetworkError::Disconnected),
+        };
+        if let Err(error) = &result {
+            _ = self.send(&NetMessage::Error(error.to_string()));
+        }
+        result
+    }
+}
+
+impl Drop for NetworkSession {
+    fn drop(&mut self) {
+        _ = self.send(&NetMessage::Quit);
+        _ = self.stream.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Placement;
+
+    fn mv(path: &str) -> Move {
+        Move {
+            path: path.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn long_lines_are_refused() {
+        let input = "move 4\r\nquit".to_string();
+        let lines = limited_lines(input.as_bytes()).collect::<Result<Vec<_>, _>>();
+        assert_eq!(lines.unwrap(), ["move 4", "quit"]);
+
+        let line = "x".repeat(MAX_LINE_LENGTH);
+        let input = format!("{line}\n{line}x\n");
+        let mut lines = limited_lines(input.as_bytes());
+        assert_eq!(lines.next().unwrap().unwrap(), line);
+        assert!(lines.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        for line in [
+            "hello layers=3 side=cross placement=sent-to draws=majority",
+            "move 4.0.8",
+            "error it is not your turn",
+            "quit",
+        ] {
+            assert_eq!(line.parse::<NetMessage>().unwrap().to_string(), line);
+        }
+        assert!("hello layers=2".parse::<NetMessage>().is_err());
+        assert!("hello layers=12 side=cross placement=free"
+            .parse::<NetMessage>()
+            .is_err());
+        assert!("hello layers=3 side=cross placement=free boards=26"
+            .parse::<NetMessage>()
+            .is_err());
+        assert!("move 9".parse::<NetMessage>().is_err());
+    }
+
+    #[test]
+    fn play_on_localhost() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let rules = Rules {
+            placement: Placement::SentTo,
+            ..Default::default()
+        };
+        let host_rules = rules.clone();
+        let host = std::thread::spawn(move || {
+            let (mut session, mut game) =
+                NetworkSession::host(&listener, 2, host_rules, State::Circle).unwrap();
+            session.play(&mut game, mv("4.0")).unwrap();
+            session.wait_for_move(&mut game).unwrap();
+            // cross tries to move out of turn
+            assert!(matches!(
+                session.wait_for_move(&mut game),
+                Err(NetworkError::OpponentOutOfTurn)
+            ));
+            game.to_notation()
+        });
+
+        let (mut session, mut game) = NetworkSession::join(address).unwrap();
+        assert_eq!(session.side(), State::Cross);
+        assert_eq!(game.rules(), &rules);
+        assert!(matches!(
+            session.play(&mut game, mv("0.0")),
+            Err(NetworkError::NotYourTurn)
+        ));
+        session.wait_for_move(&mut game).unwrap();
+        assert!(matches!(
+            session.play(&mut game, mv("4.1")),
+            Err(NetworkError::IllegalMove(MoveError::OutsideActiveBoard))
+        ));
+        session.play(&mut game, mv("0.4")).unwrap();
+
+        session.send(&NetMessage::Move(mv("4.4"))).unwrap();
+        assert_eq!(host.join().unwrap(), game.to_notation());
+        assert!(matches!(
+            session.wait_for_move(&mut game),
+            Err(NetworkError::Opponent(_))
+        ));
+    }
+}
@@ -0,0 +1,358 @@
+//! A single line notation for positions
+//!
+//! A board is written as its elements row by row, starting from the bottom left (`x + y * size`),
+//! `O` and `X` are cells owned by circle and cross, a number is that many empty cells in a row,
+//! and a sub-board is written the same way inside of `[` and `]`, with an `O` or `X` in front of it once it has been won.
+//! So the empty single layer board is `9`, and `[O8]9[9]...` has a circle in the bottom left cell of the bottom left
+//! sub-board. A plain `O` or `X` in place of a sub-board is also accepted, for a won board whose contents are unknown.
+//! The size of the boards isn't part of the notation, it comes from the [`Rules`] the position is read with
+//!
+//! A game position is the board followed by the player to move, the active board as a cell path (`-` for anywhere),
+//! and the number of moves played, separated by spaces: `[O8][9][9][9][9][9][9][9][9] X 0 1`
+
+use crate::{Board, CellPath, Element, Game, Rules, State};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsePositionError {
+    UnexpectedEnd,
+    UnexpectedCharacter(char),
+    TooManyCells,
+    TooManyLayers,
+    MissingField(&'static str),
+    InvalidField(String),
+}
+
+impl std::fmt::Display for ParsePositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParsePositionError::UnexpectedEnd => write!(f, "the board ended early"),
+            ParsePositionError::UnexpectedCharacter(c) => write!(f, "unexpected '{c}'"),
+            ParsePositionError::TooManyCells => write!(f, "a board has too many cells"),
+            ParsePositionError::TooManyLayers => {
+                write!(
+                    f,
+                    "boards can't be nested more than {} deep",
+                    Rules::MAX_LAYERS
+                )
+            }
+            ParsePositionError::MissingField(field) => write!(f, "the {field} is missing"),
+            ParsePositionError::InvalidField(field) => write!(f, "'{field}' is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for ParsePositionError {}
+
+impl std::fmt::Display for Board {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut empty_cells = 0;
+        for y in 0..self.size() {
+            for x in 0..self.size() {
+                let element = &self.elements[x][y];
+                if let Element::State(None) = element {
+                    empty_cells += 1;
+                    continue;
+                }
+                if empty_cells > 0 {
+                    write!(f, "{empty_cells}")?;
+                    empty_cells = 0;
+                }
+                match element {
+                    Element::State(None) => unreachable!(),
+                    Element::State(Some(State::Circle)) => write!(f, "O")?,
+                    Element::State(Some(State::Cross)) => write!(f, "X")?,
+                    Element::Board(board) => write!(f, "[{board}]")?,
+                    Element::Won { winner, board } => match winner {
+                        State::Circle => write!(f, "O[{board}]")?,
+                        State::Cross => write!(f, "X[{board}]")?,
+                    },
+                }
+            }
+        }
+        if empty_cells > 0 {
+            write!(f, "{empty_cells}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a board made of classic three by three boards
+impl std::str::FromStr for Board {
+    type Err = ParsePositionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Board::from_notation(s, &Rules::default())
+    }
+}
+
+impl Board {
+    /// Reads a board with the board shapes of `rules`, with at most [`Rules::MAX_LAYERS`] layers
+    pub fn from_notation(s: &str, rules: &Rules) -> Result<Board, ParsePositionError> {
+        fn parse_board(
+            chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+            rules: &Rules,
+            layer: usize,
+        ) -> Result<Board, ParsePositionError> {
+            let shape = rules.shape(layer);
+            let num_cells = shape.size * shape.size;
+            let mut elements = Vec::with_capacity(num_cells);
+            while elements.len() < num_cells {
+                match chars.next().ok_or(ParsePositionError::UnexpectedEnd)? {
+                    c @ ('O' | 'X') => {
+                        let state = if c == 'O' {
+                            State::Circle
+                        } else {
+                            State::Cross
+                        };
+                        if chars.next_if_eq(&'[').is_some() {
+                            let board = parse_inner_board(chars, rules, layer + 1)?;
+                            elements.push(Element::Won {
+                                winner: state,
+                                board: Box::new(board),
+                            });
+                        } else {
+                            elements.push(Element::State(Some(state)));
+                        }
+                    }
+                    '[' => {
+                        let inner = parse_inner_board(chars, rules, layer + 1)?;
+                        elements.push(Element::Board(Box::new(inner)));
+                    }
+                    c @ '1'..='9' => {
+                        let mut count = c.to_digit(10).unwrap() as usize;
+                        // checked after every digit, so a long number can't overflow
+                        loop {
+                            if elements.len() + count > num_cells {
+                                return Err(ParsePositionError::TooManyCells);
+                            }
+                            let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) else {
+                                break;
+                            };
+                            chars.next();
+                            count = count * 10 + digit as usize;
+                        }
+                        elements.resize_with(elements.len() + count, Element::default);
+                    }
+                    c => return Err(ParsePositionError::UnexpectedCharacter(c)),
+                }
+            }
+
+            let mut board = Board::empty(shape);
+            for (index, element) in elements.into_iter().enumerate() {
+                board.elements[index % shape.size][index / shape.size] = element;
+            }
+            Ok(board)
+        }
+
+        /// Parses a sub-board after its opening bracket
+        fn parse_inner_board(
+            chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+            rules: &Rules,
+            layer: usize,
+        ) -> Result<Board, ParsePositionError> {
+            if layer >= Rules::MAX_LAYERS {
+                return Err(ParsePositionError::TooManyLayers);
+            }
+            let board = parse_board(chars, rules, layer)?;
+            match chars.next() {
+                Some(']') => Ok(board),
+                Some(c) => Err(ParsePositionError::UnexpectedCharacter(c)),
+                None => Err(ParsePositionError::UnexpectedEnd),
+            }
+        }
+
+        let mut chars = s.trim().chars().peekable();
+        let board = parse_board(&mut chars, rules, 0)?;
+        match chars.next() {
+            Some(c) => Err(ParsePositionError::UnexpectedCharacter(c)),
+            None => Ok(board),
+        }
+    }
+}
+
+impl Game {
+    pub fn to_notation(&self) -> String {
+        let turn = match self.current_player() {
+            State::Circle => 'O',
+            State::Cross => 'X',
+        };
+        format!(
+            "{} {turn} {} {}",
+            self.board(),
+            self.active_board(),
+            self.num_moves()
+        )
+    }
+
+    pub fn from_notation(s: &str, rules: Rules) -> Result<Game, ParsePositionError> {
+        let mut fields = s.split_whitespace();
+        let board = Board::from_notation(
+            fields
+                .next()
+                .ok_or(ParsePositionError::MissingField("board"))?,
+            &rules,
+        )?;
+        let turn = match fields
+            .next()
+            .ok_or(ParsePositionError::MissingField("player to move"))?
+        {
+            "O" => State::Circle,
+            "X" => State::Cross,
+            field => return Err(ParsePositionError::InvalidField(field.to_string())),
+        };
+        let active_field = fields.next();
+        let active_board = match active_field {
+            Some(field) => field
+                .parse::<CellPath>()
+                .map_err(|_| ParsePositionError::InvalidField(field.to_string()))?,
+            None => CellPath::root(),
+        };
+        let num_moves = match fields.next() {
+            Some(field) => field
+                .parse()
+                .map_err(|_| ParsePositionError::InvalidField(field.to_string()))?,
+            None => 0,
+        };
+        if let Some(field) = fields.next() {
+            return Err(ParsePositionError::InvalidField(field.to_string()));
+        }
+
+        let game = Game::from_position(board, turn, active_board, num_moves, rules);
+        // the active board has to be a board that can still be played in, which is only known once it is collapsed
+        let active_board = game.active_board();
+        if !active_board.is_empty() && game.game_state().is_closed(active_board) {
+            return Err(ParsePositionError::InvalidField(
+                active_field.unwrap_or_default().to_string(),
+            ));
+        }
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BoardShape, Move, Outcome, Placement};
+
+    #[test]
+    fn board_round_trip() {
+        for notation in ["9", "O3X3O", "[9][9][9][9][X8][9][9][9][9]", "[[9]8]8"] {
+            let board = notation.parse::<Board>().unwrap();
+            assert_eq!(board.to_string(), notation);
+        }
+        assert_eq!(Board::new(2).to_string(), "[9][9][9][9][9][9][9][9][9]");
+    }
+
+    #[test]
+    fn one_line_positions() {
+        assert_eq!(
+            "XXX6".parse::<Board>().unwrap().get_winner(),
+            Some(State::Cross)
+        );
+        assert_eq!(
+            "O3O3O".parse::<Board>().unwrap().get_winner(),
+            Some(State::Circle)
+        );
+        assert!("OXOOXXXOO".parse::<Board>().unwrap().is_stalemate());
+        assert_eq!(
+            "OOO6OOO".parse::<Board>().err(),
+            Some(ParsePositionError::UnexpectedCharacter('O'))
+        );
+        assert_eq!(
+            "[9]7".parse::<Board>().err(),
+            Some(ParsePositionError::UnexpectedEnd)
+        );
+        assert_eq!(
+            "O9".parse::<Board>().err(),
+            Some(ParsePositionError::TooManyCells)
+        );
+    }
+
+    #[test]
+    fn hostile_positions_are_rejected() {
+        assert_eq!(
+            "199999999999999999999999".parse::<Board>().err(),
+            Some(ParsePositionError::TooManyCells)
+        );
+        assert_eq!(
+            Game::from_notation("199999999999999999999999 X - 0", Rules::default()).err(),
+            Some(ParsePositionError::TooManyCells)
+        );
+
+        let nested = |depth: usize| {
+            let mut notation = "9".to_string();
+            for _ in 1..depth {
+                notation = format!("[{notation}]8");
+            }
+            notation
+        };
+        assert!(nested(Rules::MAX_LAYERS).parse::<Board>().is_ok());
+        assert_eq!(
+            nested(Rules::MAX_LAYERS + 1).parse::<Board>().err(),
+            Some(ParsePositionError::TooManyLayers)
+        );
+        assert_eq!(
+            "[".repeat(1_000_000).parse::<Board>().err(),
+            Some(ParsePositionError::TooManyLayers)
+        );
+    }
+
+    #[test]
+    fn game_round_trip() {
+        let rules = Rules {
+            placement: Placement::SentTo,
+            ..Default::default()
+        };
+        let mut game = Game::new(2, rules.clone());
+        game.play(Move {
+            path: "4.0".parse().unwrap(),
+        })
+        .unwrap();
+        let notation = game.to_notation();
+        assert_eq!(notation, "[9][9][9][9][O8][9][9][9][9] X 0 1");
+
+        let loaded = Game::from_notation(&notation, rules).unwrap();
+        assert_eq!(loaded.to_notation(), notation);
+        assert_eq!(loaded.legal_moves().len(), 9);
+        assert_eq!(loaded.start_position(), Some(notation.as_str()));
+    }
+
+    #[test]
+    fn won_sub_boards_are_collapsed() {
+        let game = Game::from_notation("[OOO6]8 X", Rules::default()).unwrap();
+        assert_eq!(game.to_notation(), "O[OOO6]8 X - 0");
+        assert_eq!(game.legal_moves().len(), 8);
+        let game = Game::from_notation("O8 X", Rules::default()).unwrap();
+        assert_eq!(game.to_notation(), "O8 X - 0");
+        assert_eq!(game.status(), Outcome::Ongoing);
+        assert!(Game::from_notation("9 X 4", Rules::default()).is_err());
+
+        // the active board can't be one that has been won or drawn
+        assert_eq!(
+            Game::from_notation("[OOO6]8 X 0", Rules::default()).err(),
+            Some(ParsePositionError::InvalidField("0".into()))
+        );
+        assert!(Game::from_notation("[OXOOXXXOO]8 X 0", Rules::default()).is_err());
+        assert!(Game::from_notation("[[OOO6]8]8 X 0.0", Rules::default()).is_err());
+        let game = Game::from_notation("[OOO6][9]7 X 1", Rules::default()).unwrap();
+        assert_eq!(game.legal_moves().len(), 9);
+    }
+
+    #[test]
+    fn other_board_shapes() {
+        let rules = Rules {
+            shapes: vec![BoardShape {
+                size: 4,
+                win_length: 3,
+            }],
+            ..Default::default()
+        };
+        let game = Game::from_notation("1OOO12 X", rules.clone()).unwrap();
+        assert_eq!(game.status(), Outcome::Won(State::Circle));
+        assert_eq!(game.to_notation(), "1OOO12 X - 0");
+        assert_eq!(
+            Game::from_notation("17 X", rules).err(),
+            Some(ParsePositionError::TooManyCells)
+        );
+    }
+}
@@ -8,6 +8,8 @@ pub struct PerObjectData {
     pub rotation: f32,
     pub scale: cgmath::Vector2<f32>,
     pub color: cgmath::Vector3<f32>,
+    /// How opaque the object is, for drawing highlights over the board
+    pub alpha: f32,
     pub is_circle: u32,
     pub circle_width: f32,
 }
@@ -38,6 +40,11 @@ impl PerObjectData {
             shader_location: 3,
             format: wgpu::VertexFormat::Float32x3,
         },
+        wgpu::VertexAttribute {
+            offset: offset_of!(Self, alpha) as wgpu::BufferAddress,
+            shader_location: 6,
+            format: wgpu::VertexFormat::Float32,
+        },
         wgpu::VertexAttribute {
             offset: offset_of!(Self, is_circle) as wgpu::BufferAddress,
             shader_location: 4,
@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use crate::{Bot, EngineBot, GreedyBot, MctsBot, MinimaxBot, RandomBot};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlayerKind {
+    #[default]
+    Human,
+    Random,
+    Greedy,
+    Minimax,
+    Mcts,
+    /// An external program talking the engine protocol
+    Engine,
+}
+
+impl std::fmt::Display for PlayerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlayerKind::Human => write!(f, "Human"),
+            PlayerKind::Random => write!(f, "Random"),
+            PlayerKind::Greedy => write!(f, "Greedy"),
+            PlayerKind::Minimax => write!(f, "Minimax"),
+            PlayerKind::Mcts => write!(f, "Monte Carlo"),
+            PlayerKind::Engine => write!(f, "Engine"),
+        }
+    }
+}
+
+impl PlayerKind {
+    /// The name used for this kind of player on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            PlayerKind::Human => "human",
+            PlayerKind::Random => "random",
+            PlayerKind::Greedy => "greedy",
+            PlayerKind::Minimax => "minimax",
+            PlayerKind::Mcts => "mcts",
+            PlayerKind::Engine => "engine",
+        }
+    }
+}
+
+impl std::str::FromStr for PlayerKind {
+    type Err = UnknownPlayerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PlayerSettings::KINDS
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| UnknownPlayerError(s.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPlayerError(pub String);
+
+impl std::fmt::Display for UnknownPlayerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown player '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownPlayerError {}
+
+/// How one side of the game is played
+#[derive(Debug, Clone)]
+pub struct PlayerSettings {
+    pub kind: PlayerKind,
+    /// The seed for the random and greedy players
+    pub seed: u64,
+    pub minimax: MinimaxBot,
+    pub mcts: MctsBot,
+    /// The command starting the engine, with arguments separated by whitespace
+    pub engine_command: String,
+    pub engine_movetime: Duration,
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        Self {
+            kind: PlayerKind::Human,
+            seed: 0,
+            minimax: MinimaxBot::default(),
+            mcts: MctsBot::default(),
+            engine_command: String::new(),
+            engine_movetime: Duration::from_secs(1),
+        }
+    }
+}
+
+impl PlayerSettings {
+    pub const KINDS: [PlayerKind; 6] = [
+        PlayerKind::Human,
+        PlayerKind::Random,
+        PlayerKind::Greedy,
+        PlayerKind::Minimax,
+        PlayerKind::Mcts,
+        PlayerKind::Engine,
+    ];
+
+    /// Returns the bot for this side, or `None` for a human player
+    ///
+    /// Fails if the engine could not be started
+    pub fn make_bot(&self) -> std::io::Result<Option<Box<dyn Bot>>> {
+        Ok(match self.kind {
+            PlayerKind::Human => None,
+            PlayerKind::Random => Some(Box::new(RandomBot::new(self.seed))),
+            PlayerKind::Greedy => Some(Box::new(GreedyBot::new(self.seed))),
+            PlayerKind::Minimax => Some(Box::new(self.minimax)),
+            PlayerKind::Mcts => Some(Box::new(self.mcts.clone())),
+            PlayerKind::Engine => Some(Box::new(EngineBot::spawn(
+                &self.engine_command,
+                self.engine_movetime,
+            )?)),
+        })
+    }
+
+    pub fn ui(&mut self, ui: &mut eframe::egui::Ui, id: &str) -> bool {
+        use eframe::egui;
+
+        let mut changed = false;
+        egui::ComboBox::from_id_source(id)
+            .selected_text(self.kind.to_string())
+            .show_ui(ui, |ui| {
+                for kind in Self::KINDS {
+                    changed |= ui
+                        .selectable_value(&mut self.kind, kind, kind.to_string())
+                        .changed();
+                }
+            });
+        match self.kind {
+            PlayerKind::Human => {}
+            PlayerKind::Random | PlayerKind::Greedy => {
+                ui.horizontal(|ui| {
+                    ui.label("Seed:");
+                    changed |= ui.add(egui::DragValue::new(&mut self.seed)).changed();
+                });
+            }
+            PlayerKind::Minimax => {
+                ui.horizontal(|ui| {
+                    ui.label("Depth:");
+                    changed |= ui
+                        .add(egui::DragValue::new(&mut self.minimax.max_depth).clamp_range(1..=12))
+                        .changed();
+                    changed |= time_limit_ui(ui, &mut self.minimax.time_limit);
+                });
+            }
+            PlayerKind::Mcts => {
+                ui.horizontal(|ui| {
+                    ui.label("Iterations:");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut self.mcts.iterations)
+                                .clamp_range(1..=10_000_000)
+                                .speed(100),
+                        )
+                        .changed();
+                    changed |= time_limit_ui(ui, &mut self.mcts.time_limit);
+                    ui.label("Seed:");
+                    changed |= ui.add(egui::DragValue::new(&mut self.mcts.seed)).changed();
+                });
+            }
+            PlayerKind::Engine => {
+                ui.horizontal(|ui| {
+                    ui.label("Command:");
+                    changed |= ui
+                        .text_edit_singleline(&mut self.engine_command)
+                        .lost_focus();
+                });
+                ui.horizontal(|ui| {
+                    changed |= time_limit_ui(ui, &mut self.engine_movetime);
+                });
+            }
+        }
+        changed
+    }
+}
+
+fn time_limit_ui(ui: &mut eframe::egui::Ui, time_limit: &mut Duration) -> bool {
+    use eframe::egui;
+
+    ui.label("Time (ms):");
+    let mut millis = time_limit.as_millis() as u64;
+    let changed = ui
+        .add(
+            egui::DragValue::new(&mut millis)
+                .clamp_range(10..=60_000)
+                .speed(10),
+        )
+        .changed();
+    if changed {
+        *time_limit = Duration::from_millis(millis);
+    }
+    changed
+}
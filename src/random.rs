@@ -0,0 +1,130 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::{Bot, Game, Move, Outcome};
+
+/// Plays a random legal move
+#[derive(Debug, Clone)]
+pub struct RandomBot {
+    rng: StdRng,
+}
+
+impl RandomBot {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Bot for RandomBot {
+    fn name(&self) -> String {
+        "Random".into()
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Move> {
+        game.legal_moves().choose(&mut self.rng).cloned()
+    }
+}
+
+/// Wins the game or a sub-board if it can, and otherwise plays a random legal move
+#[derive(Debug, Clone)]
+pub struct GreedyBot {
+    rng: StdRng,
+}
+
+impl GreedyBot {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Bot for GreedyBot {
+    fn name(&self) -> String {
+        "Greedy".into()
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Move> {
+        let moves = game.legal_moves();
+        let player = game.current_player();
+        let misere = game.rules().misere;
+        let mut game = game.clone();
+        let mut value = |mv: &Move| {
+            let outcome = game.play(mv.clone()).unwrap();
+            let parent = &mv.path[..mv.path.len() - 1];
+            // the board the move was played in may have been collapsed into a board that was won as well
+            let board_winner = game
+                .game_state()
+                .board_winner(parent)
+                .map(|line_owner| misere.sub_board_winner(line_owner));
+            game.take_back();
+            match (outcome, board_winner) {
+                (Outcome::Won(winner), _) if winner == player => 2,
+                // in misère a completed line can lose the game
+                (Outcome::Won(_), _) => -1,
+                (_, Some(winner)) if winner == player && !parent.is_empty() => 1,
+                _ => 0,
+            }
+        };
+        let values = moves.iter().map(&mut value).collect::<Vec<_>>();
+        let best = values.iter().copied().max()?;
+        let best_moves = moves
+            .into_iter()
+            .zip(values)
+            .filter(|&(_, value)| value == best)
+            .map(|(mv, _)| mv)
+            .collect::<Vec<_>>();
+        best_moves.choose(&mut self.rng).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Misere, Rules};
+
+    #[test]
+    fn greedy_wins_sub_boards() {
+        let mut game = Game::new(2, Rules::default());
+        for path in ["0.0", "8.8", "0.1", "8.7"] {
+            game.play(Move {
+                path: path.parse().unwrap(),
+            })
+            .unwrap();
+        }
+        let mv = GreedyBot::new(0).choose_move(&game).unwrap();
+        assert_eq!(mv.path.to_string(), "0.2");
+
+        let game = Game::from_notation("OO1XX4 O", Rules::default()).unwrap();
+        let mv = GreedyBot::new(0).choose_move(&game).unwrap();
+        assert_eq!(mv.path.to_string(), "2");
+
+        let rules = Rules {
+            misere: Misere::TopLevel,
+            ..Default::default()
+        };
+        let game = Game::from_notation("OO1XX4 O", rules).unwrap();
+        let mv = GreedyBot::new(0).choose_move(&game).unwrap();
+        assert_ne!(mv.path.to_string(), "2");
+    }
+
+    #[test]
+    fn greedy_wins_boards_that_collapse_further() {
+        let mut game = Game::new(3, Rules::default());
+        let circle = [
+            "0.0.0", "0.0.1", "0.0.2", "0.1.0", "0.1.1", "0.1.2", "0.2.0", "0.2.1",
+        ];
+        for (circle, cross) in circle.into_iter().zip(1..) {
+            for path in [circle.to_string(), format!("8.{cross}.0")] {
+                game.play(Move {
+                    path: path.parse().unwrap(),
+                })
+                .unwrap();
+            }
+        }
+        // winning 0.2 also wins 0
+        let mv = GreedyBot::new(0).choose_move(&game).unwrap();
+        assert_eq!(mv.path.to_string(), "0.2.2");
+    }
+}
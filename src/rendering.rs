@@ -115,10 +115,7 @@ impl RenderState {
                         entry_point: "fs_main",
                         targets: &[Some(wgpu::ColorTargetState {
                             format: wgpu_render_state.target_format,
-                            blend: Some(wgpu::BlendState {
-                                color: wgpu::BlendComponent::REPLACE,
-                                alpha: wgpu::BlendComponent::REPLACE,
-                            }),
+                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                             write_mask: wgpu::ColorWrites::ALL,
                         })],
                     }),
@@ -175,6 +172,8 @@ impl RenderState {
         }
     }
 
+    // the byte sizes are spelled out to match the buffer they describe
+    #[allow(clippy::manual_slice_size_calculation)]
     pub fn prepare(
         &mut self,
         camera: Camera,
@@ -0,0 +1,97 @@
+use crate::{Game, Move};
+
+/// Steps through the moves of a game, by taking them back and playing them again on a copy of it
+#[derive(Debug, Clone)]
+pub struct Replay {
+    game: Game,
+    len: usize,
+}
+
+impl Replay {
+    /// Starts at the end of `game`
+    pub fn new(game: &Game) -> Self {
+        Self {
+            game: game.clone(),
+            len: game.moves().count(),
+        }
+    }
+
+    /// The game as it was after the current move
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// The number of moves in the replay
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// How many moves have been played at the current point of the replay
+    pub fn position(&self) -> usize {
+        self.game.moves().count()
+    }
+
+    pub fn last_move(&self) -> Option<&Move> {
+        self.game.moves().last()
+    }
+
+    /// Moves to the point after `position` moves, clamped to the length of the replay
+    pub fn seek(&mut self, position: usize) {
+        let position = position.min(self.len);
+        while self.position() > position && self.game.undo().is_some() {}
+        while self.position() < position && self.game.redo().is_some() {}
+    }
+
+    pub fn step_forward(&mut self) {
+        self.seek(self.position() + 1);
+    }
+
+    pub fn step_back(&mut self) {
+        self.seek(self.position().saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CellPath, Rules};
+
+    #[test]
+    fn scrubbing() {
+        let mut game = Game::new(2, Rules::default());
+        for path in ["0.0", "4.0", "0.1", "4.1", "0.2", "8.8"] {
+            game.play(Move {
+                path: path.parse().unwrap(),
+            })
+            .unwrap();
+        }
+        let mut replay = Replay::new(&game);
+        assert_eq!((replay.position(), replay.len()), (6, 6));
+
+        replay.seek(2);
+        assert_eq!(
+            replay.game().to_notation(),
+            "[O8][9][9][9][X8][9][9][9][9] O - 2"
+        );
+        replay.step_forward();
+        replay.step_forward();
+        replay.step_forward();
+        assert_eq!(replay.last_move().unwrap().path.to_string(), "0.2");
+        assert_eq!(
+            replay.game().last_collapsed(),
+            Some(&CellPath(vec![(0, 0)]))
+        );
+        replay.step_back();
+        assert_eq!(replay.game().last_collapsed(), None);
+
+        replay.seek(100);
+        assert_eq!(replay.game().to_notation(), game.to_notation());
+        replay.seek(0);
+        replay.step_back();
+        assert_eq!(replay.position(), 0);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::{Board, BoardShape, CellPath, GameState, State};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Placement {
+    /// A move can be made in any empty cell
+    #[default]
+    Free,
+    /// A move sends the next player to the board matching the cell that was just played.
+    /// With more than two layers every layer of the move picks the board one layer further out, so a move in cell
+    /// `a.b.c` sends the next player to board `b.c`. If that board is won or full, the choice is free inside of `b`,
+    /// and anywhere if `b` is closed as well
+    SentTo,
+}
+
+impl std::fmt::Display for Placement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Placement::Free => write!(f, "Free placement"),
+            Placement::SentTo => write!(f, "Sent-to board"),
+        }
+    }
+}
+
+impl Placement {
+    /// The name used for this placement rule in text formats
+    pub fn name(self) -> &'static str {
+        match self {
+            Placement::Free => "free",
+            Placement::SentTo => "sent-to",
+        }
+    }
+}
+
+impl std::str::FromStr for Placement {
+    type Err = UnknownRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Placement::Free, Placement::SentTo]
+            .into_iter()
+            .find(|placement| placement.name() == s)
+            .ok_or_else(|| UnknownRuleError(s.to_string()))
+    }
+}
+
+/// How a drawn sub-board counts when looking for three in a row on the board containing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DrawPolicy {
+    /// A drawn board counts for nobody
+    #[default]
+    Dead,
+    /// A drawn board counts for both players, but a line needs at least one board that was actually won
+    CountsForBoth,
+    /// A drawn board counts for the player who owns more of its cells, and for nobody if that is even
+    MajorityWins,
+}
+
+impl std::fmt::Display for DrawPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DrawPolicy::Dead => write!(f, "Draws are dead"),
+            DrawPolicy::CountsForBoth => write!(f, "Draws count for both"),
+            DrawPolicy::MajorityWins => write!(f, "Draws go to the majority"),
+        }
+    }
+}
+
+impl DrawPolicy {
+    pub const ALL: [DrawPolicy; 3] = [
+        DrawPolicy::Dead,
+        DrawPolicy::CountsForBoth,
+        DrawPolicy::MajorityWins,
+    ];
+
+    /// The name used for this draw policy in text formats
+    pub fn name(self) -> &'static str {
+        match self {
+            DrawPolicy::Dead => "dead",
+            DrawPolicy::CountsForBoth => "both",
+            DrawPolicy::MajorityWins => "majority",
+        }
+    }
+}
+
+impl std::str::FromStr for DrawPolicy {
+    type Err = UnknownRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DrawPolicy::ALL
+            .into_iter()
+            .find(|policy| policy.name() == s)
+            .ok_or_else(|| UnknownRuleError(s.to_string()))
+    }
+}
+
+/// Whether completing a line loses instead of winning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Misere {
+    /// Completing a line wins, on every layer
+    #[default]
+    Normal,
+    /// Completing a line on the outermost board loses the game, sub-boards are still won by completing a line
+    TopLevel,
+    /// Completing a line loses on every layer, a sub-board goes to the opponent of the player who completed it
+    AllLayers,
+}
+
+impl std::fmt::Display for Misere {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Misere::Normal => write!(f, "Normal"),
+            Misere::TopLevel => write!(f, "Misère"),
+            Misere::AllLayers => write!(f, "Misère on every board"),
+        }
+    }
+}
+
+impl Misere {
+    pub const ALL: [Misere; 3] = [Misere::Normal, Misere::TopLevel, Misere::AllLayers];
+
+    /// The name used for this variant in text formats
+    pub fn name(self) -> &'static str {
+        match self {
+            Misere::Normal => "off",
+            Misere::TopLevel => "top",
+            Misere::AllLayers => "all",
+        }
+    }
+
+    /// The player who gets a sub-board that `line_owner` completed a line in
+    pub fn sub_board_winner(self, line_owner: State) -> State {
+        match self {
+            Misere::Normal | Misere::TopLevel => line_owner,
+            Misere::AllLayers => line_owner.opponent(),
+        }
+    }
+
+    /// The player who wins the game when `line_owner` completed a line on the outermost board
+    pub fn game_winner(self, line_owner: State) -> State {
+        match self {
+            Misere::Normal => line_owner,
+            Misere::TopLevel | Misere::AllLayers => line_owner.opponent(),
+        }
+    }
+}
+
+impl std::str::FromStr for Misere {
+    type Err = UnknownRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Misere::ALL
+            .into_iter()
+            .find(|misere| misere.name() == s)
+            .ok_or_else(|| UnknownRuleError(s.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownRuleError(pub String);
+
+impl std::fmt::Display for UnknownRuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown rule '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownRuleError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Rules {
+    pub placement: Placement,
+    pub draw_policy: DrawPolicy,
+    pub misere: Misere,
+    /// The shape of the boards on each layer starting from the outermost one, the last shape is used for
+    /// all the layers below it and no shapes at all means classic three by three boards
+    pub shapes: Vec<BoardShape>,
+}
+
+impl Rules {
+    /// The most layers a game can have when it is read from another program or a file
+    pub const MAX_LAYERS: usize = 5;
+    /// The most cells a game can have on its deepest layer when it is read from another program or a file,
+    /// as many as five layers of classic boards have
+    pub const MAX_CELLS: usize = 9usize.pow(5);
+
+    /// Whether a game with `num_layers` layers is within [`Rules::MAX_LAYERS`] and [`Rules::MAX_CELLS`],
+    /// anything bigger from outside of the program could take all of the memory
+    pub fn within_limits(&self, num_layers: usize) -> bool {
+        (1..=Self::MAX_LAYERS).contains(&num_layers)
+            && self
+                .layer_shapes(num_layers)
+                .iter()
+                .try_fold(1usize, |cells, shape| {
+                    cells
+                        .checked_mul(shape.size * shape.size)
+                        .filter(|&cells| cells <= Self::MAX_CELLS)
+                })
+                .is_some()
+    }
+
+    /// The shape of the boards on `layer`, where the outermost board is on layer 0
+    pub fn shape(&self, layer: usize) -> BoardShape {
+        self.shapes
+            .get(layer)
+            .or(self.shapes.last())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The winner of the game on `board`, taking the draw policy and misère into account
+    pub fn winner(&self, board: &Board) -> Option<State> {
+        board
+            .get_winner_with(self.draw_policy)
+            .map(|line_owner| self.misere.game_winner(line_owner))
+    }
+
+    /// The shapes of all the layers of a game with `num_layers` layers
+    pub fn layer_shapes(&self, num_layers: usize) -> Vec<BoardShape> {
+        (0..num_layers).map(|layer| self.shape(layer)).collect()
+    }
+
+    /// The rules as names and values for text formats, rules other than the placement are left out when
+    /// they have their default value
+    pub fn options(&self) -> Vec<(&'static str, String)> {
+        let mut options = vec![("placement", self.placement.name().to_string())];
+        if self.draw_policy != DrawPolicy::default() {
+            options.push(("draws", self.draw_policy.name().to_string()));
+        }
+        if self.misere != Misere::default() {
+            options.push(("misere", self.misere.name().to_string()));
+        }
+        if !self.shapes.is_empty() {
+            let shapes = self.shapes.iter().map(BoardShape::to_string);
+            options.push(("boards", shapes.collect::<Vec<_>>().join(",")));
+        }
+        options
+    }
+
+    /// Sets the rule called `name` from its value in a text format, returns `false` if there is no such rule
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<bool, UnknownRuleError> {
+        match name {
+            "placement" => self.placement = value.parse()?,
+            "draws" => self.draw_policy = value.parse()?,
+            "misere" => self.misere = value.parse()?,
+            "boards" => {
+                self.shapes = value
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| UnknownRuleError(value.to_string()))?
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Returns the path of the board the next move has to be played in, an empty path means anywhere
+    ///
+    /// `played` is the path of the cell that was just played, `game_state` must already include it
+    pub fn next_active_board(&self, game_state: &GameState, played: &[(usize, usize)]) -> CellPath {
+        match self.placement {
+            Placement::Free => CellPath::root(),
+            Placement::SentTo => {
+                // the outermost layer of the move is dropped, so the position of the move in each of the other layers
+                // picks the board in the same position one layer further out
+                let Some((_, inner)) = played.split_first() else {
+                    return CellPath::root();
+                };
+                let mut active_board = CellPath(inner.to_vec());
+
+                // if that board cant be played in or doesnt exist because the layers have different sizes,
+                // the choice is free inside of the board containing it
+                while !active_board.is_empty() && game_state.is_closed(&active_board) {
+                    active_board.0.pop();
+                }
+                active_board
+            }
+        }
+    }
+
+    pub fn is_move_allowed(active_board: &[(usize, usize)], path: &[(usize, usize)]) -> bool {
+        path.starts_with(active_board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Element, State};
+
+    fn state(board: &Board, rules: &Rules) -> GameState {
+        GameState::new(board, rules.draw_policy, rules.misere)
+    }
+
+    fn two_layer_board() -> Board {
+        let mut board = Board::default();
+        for element in board.elements.iter_mut().flatten() {
+            *element = Element::Board(Box::default());
+        }
+        board
+    }
+
+    #[test]
+    fn free_placement_is_never_restricted() {
+        let board = two_layer_board();
+        let rules = Rules {
+            placement: Placement::Free,
+            ..Default::default()
+        };
+        assert!(rules
+            .next_active_board(&state(&board, &rules), &[(0, 0), (2, 1)])
+            .is_empty());
+    }
+
+    #[test]
+    fn sent_to_matching_board() {
+        let board = two_layer_board();
+        let rules = Rules {
+            placement: Placement::SentTo,
+            ..Default::default()
+        };
+        let active_board = rules.next_active_board(&state(&board, &rules), &[(0, 0), (2, 1)]);
+        assert_eq!(active_board, CellPath(vec![(2, 1)]));
+        assert!(Rules::is_move_allowed(&active_board, &[(2, 1), (0, 0)]));
+        assert!(!Rules::is_move_allowed(&active_board, &[(0, 0), (0, 0)]));
+    }
+
+    #[test]
+    fn sent_to_closed_board_is_free() {
+        let mut board = two_layer_board();
+        board.elements[2][1] = Element::State(Some(State::Cross));
+        let rules = Rules {
+            placement: Placement::SentTo,
+            ..Default::default()
+        };
+        assert!(rules
+            .next_active_board(&state(&board, &rules), &[(0, 0), (2, 1)])
+            .is_empty());
+    }
+
+    #[test]
+    fn sent_to_on_three_layers() {
+        let mut board = two_layer_board();
+        for element in board.elements.iter_mut().flatten() {
+            *element = Element::Board(Box::new(two_layer_board()));
+        }
+        let Element::Board(outer) = &mut board.elements[1][1] else {
+            unreachable!()
+        };
+        outer.elements[0][2] = Element::State(Some(State::Circle));
+        board.elements[2][0] = Element::State(Some(State::Cross));
+        let mut won = two_layer_board();
+        won.elements[0][0] = Element::State(Some(State::Circle));
+        board.elements[0][1] = Element::Won {
+            winner: State::Cross,
+            board: Box::new(won),
+        };
+
+        let rules = Rules {
+            placement: Placement::SentTo,
+            ..Default::default()
+        };
+        let state = state(&board, &rules);
+        // the move leaves the outermost board it was played in
+        assert_eq!(
+            rules.next_active_board(&state, &[(0, 0), (2, 2), (1, 0)]),
+            CellPath(vec![(2, 2), (1, 0)])
+        );
+        assert_eq!(
+            rules.next_active_board(&state, &[(2, 2), (1, 1), (1, 0)]),
+            CellPath(vec![(1, 1), (1, 0)])
+        );
+        // a closed board falls back to the outermost board containing it, and then to anywhere
+        assert_eq!(
+            rules.next_active_board(&state, &[(0, 0), (1, 1), (0, 2)]),
+            CellPath(vec![(1, 1)])
+        );
+        assert!(rules
+            .next_active_board(&state, &[(0, 0), (2, 0), (1, 1)])
+            .is_empty());
+        // boards inside of a won board are closed with it
+        assert!(rules
+            .next_active_board(&state, &[(0, 0), (0, 1), (1, 1)])
+            .is_empty());
+    }
+
+    #[test]
+    fn options_round_trip() {
+        let rules = Rules {
+            placement: Placement::SentTo,
+            draw_policy: DrawPolicy::MajorityWins,
+            misere: Misere::AllLayers,
+            shapes: vec![
+                BoardShape {
+                    size: 5,
+                    win_length: 4,
+                },
+                BoardShape::default(),
+            ],
+        };
+        let mut parsed = Rules::default();
+        for (name, value) in rules.options() {
+            assert_eq!(parsed.set_option(name, &value), Ok(true));
+        }
+        assert_eq!(parsed, rules);
+        assert_eq!(parsed.set_option("unknown", "value"), Ok(false));
+        assert!(parsed.set_option("draws", "nobody").is_err());
+        assert!(parsed.set_option("boards", "3:4").is_err());
+        assert_eq!(parsed.shape(0).size, 5);
+        assert_eq!(parsed.shape(7), BoardShape::default());
+    }
+}
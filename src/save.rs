@@ -0,0 +1,311 @@
+//! A plain text format for saving games
+//!
+//! Every line starts with a keyword, lines starting with `#` and empty lines are ignored:
+//!
+//! ```text
+//! # Ultimate Tic Tac Toe
+//! layers 2
+//! placement sent-to
+//! moves 4.0 0.8 8.4
+//! position
+//! ...|...|...
+//! ...
+//! ```
+//!
+//! - `layers <n>` the number of layers of the board, required unless there is a `start` line, in which case it has
+//!   to match the start position. Games with more layers or cells than [`Rules::within_limits`] allows aren't loaded
+//! - `placement <free|sent-to>` the placement rule, defaults to `free`
+//! - `draws <dead|both|majority>` how drawn sub-boards count, defaults to `dead`
+//! - `misere <off|top|all>` whether completing a line loses, on the outermost board or on every board,
+//!   defaults to `off`
+//! - `boards <size>:<in a row>,...` the board shape of each layer from the outermost one, the last shape is
+//!   used for the layers below it, defaults to three by three boards with three in a row
+//! - `start <position>` the position the game started from in position notation, defaults to an empty board
+//! - `moves <cell> ...` the moves played so far as cell paths, can be repeated to continue the list
+//! - `position` optionally followed by a snapshot of the board, one line per row of cells starting with the top,
+//!   using `.` for empty cells, `O` for circle and `X` for cross, with `|` between the boards of the outermost layer.
+//!   When loading, the moves are replayed and the snapshot has to match the resulting board.
+//!   Every line after `position` is read as part of the snapshot, so it has to be the last keyword
+
+use crate::{CellPath, Game, Move, MoveError, ParsePositionError, Rules, State};
+
+const MOVES_PER_LINE: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    MissingLayers,
+    /// The `layers` line doesn't match the number of layers of the `start` position
+    LayersMismatch {
+        layers: usize,
+        start_layers: usize,
+    },
+    /// The game has more layers or cells than [`Rules::within_limits`] allows
+    TooLarge,
+    InvalidLine {
+        line: usize,
+        message: String,
+    },
+    InvalidStart(ParsePositionError),
+    IllegalMove {
+        index: usize,
+        error: MoveError,
+    },
+    PositionMismatch,
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::MissingLayers => write!(f, "the number of layers is missing"),
+            LoadError::LayersMismatch {
+                layers,
+                start_layers,
+            } => write!(
+                f,
+                "the game has {layers} layers but the start position has {start_layers}"
+            ),
+            LoadError::TooLarge => write!(
+                f,
+                "the game has more than {} layers or {} cells",
+                Rules::MAX_LAYERS,
+                Rules::MAX_CELLS
+            ),
+            LoadError::InvalidLine { line, message } => write!(f, "line {line}: {message}"),
+            LoadError::InvalidStart(error) => write!(f, "invalid start position: {error}"),
+            LoadError::IllegalMove { index, error } => write!(f, "move {}: {error}", index + 1),
+            LoadError::PositionMismatch => {
+                write!(f, "the saved position does not match the moves")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl Game {
+    pub fn to_save_string(&self, include_position: bool) -> String {
+        let mut save = String::new();
+        save.push_str("# Ultimate Tic Tac Toe\n");
+        save.push_str(&format!("layers {}\n", self.num_layers()));
+        for (name, value) in self.rules().options() {
+            save.push_str(&format!("{name} {value}\n"));
+        }
+        if let Some(start_position) = self.start_position() {
+            save.push_str(&format!("start {start_position}\n"));
+        }
+
+        let moves = self.moves().collect::<Vec<_>>();
+        for moves in moves.chunks(MOVES_PER_LINE) {
+            save.push_str("moves");
+            for mv in moves {
+                save.push_str(&format!(" {}", mv.path));
+            }
+            save.push('\n');
+        }
+
+        if include_position {
+            save.push_str("position\n");
+            for line in self.position_lines() {
+                save.push_str(&line);
+                save.push('\n');
+            }
+        }
+        save
+    }
+
+    pub fn from_save_str(s: &str) -> Result<Game, LoadError> {
+        let mut num_layers = None;
+        let mut rules = Rules::default();
+        let mut start_position = None;
+        let mut moves = vec![];
+        let mut position = None::<Vec<String>>;
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |message: String| LoadError::InvalidLine {
+                line: i + 1,
+                message,
+            };
+
+            if let Some(position) = &mut position {
+                position.push(line.to_string());
+                continue;
+            }
+
+            let (keyword, arguments) = line.split_once(' ').unwrap_or((line, ""));
+            match keyword {
+                "layers" => match arguments.trim().parse() {
+                    Ok(layers @ 1..) => num_layers = Some(layers),
+                    _ => return Err(invalid(format!("invalid number of layers '{arguments}'"))),
+                },
+                "start" => start_position = Some(arguments.to_string()),
+                "moves" => {
+                    for path in arguments.split_whitespace() {
+                        let path = path
+                            .parse::<CellPath>()
+                            .map_err(|error| invalid(error.to_string()))?;
+                        moves.push(Move { path });
+                    }
+                }
+                "position" => position = Some(vec![]),
+                _ => {
+                    let known = rules
+                        .set_option(keyword, arguments.trim())
+                        .map_err(|error| invalid(error.to_string()))?;
+                    if !known {
+                        return Err(invalid(format!("unknown keyword '{keyword}'")));
+                    }
+                }
+            }
+        }
+
+        let mut game = match start_position {
+            Some(start_position) => {
+                let game =
+                    Game::from_notation(&start_position, rules).map_err(LoadError::InvalidStart)?;
+                match num_layers {
+                    Some(layers) if layers != game.num_layers() => {
+                        return Err(LoadError::LayersMismatch {
+                            layers,
+                            start_layers: game.num_layers(),
+                        })
+                    }
+                    _ => game,
+                }
+            }
+            None => {
+                let num_layers = num_layers.ok_or(LoadError::MissingLayers)?;
+                if !rules.within_limits(num_layers) {
+                    return Err(LoadError::TooLarge);
+                }
+                Game::new(num_layers, rules)
+            }
+        };
+        for (index, mv) in moves.into_iter().enumerate() {
+            game.play(mv)
+                .map_err(|error| LoadError::IllegalMove { index, error })?;
+        }
+
+        if let Some(position) = position {
+            if position != game.position_lines() {
+                return Err(LoadError::PositionMismatch);
+            }
+        }
+        Ok(game)
+    }
+
+    fn position_lines(&self) -> Vec<String> {
+        let grid = self.board().leaf_grid();
+        // a single layer board has no sub-boards to separate
+        let block_size = if self.board().depth() > 1 {
+            grid.len() / self.board().size()
+        } else {
+            grid.len()
+        };
+        grid.iter()
+            .map(|row| {
+                row.chunks(block_size)
+                    .map(|cells| {
+                        cells
+                            .iter()
+                            .map(|cell| match cell {
+                                None => '.',
+                                Some(State::Circle) => 'O',
+                                Some(State::Cross) => 'X',
+                            })
+                            .collect::<String>()
+                    })
+                    .collect::<Vec<_>>()
+                    .join("|")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Placement;
+
+    #[test]
+    fn save_and_load() {
+        let mut game = Game::new(
+            2,
+            Rules {
+                placement: Placement::SentTo,
+                ..Default::default()
+            },
+        );
+        for path in ["4.0", "0.4", "4.1", "1.4", "4.2"] {
+            game.play(Move {
+                path: path.parse().unwrap(),
+            })
+            .unwrap();
+        }
+
+        let save = game.to_save_string(true);
+        assert!(save.contains("moves 4.0 0.4 4.1 1.4 4.2\n"));
+        assert!(save.contains("position\n...|...|...\n...|...|...\n...|...|...\n...|OOO|...\n"));
+
+        let loaded = Game::from_save_str(&save).unwrap();
+        assert_eq!(loaded.to_save_string(true), save);
+        assert_eq!(loaded.num_moves(), 5);
+        assert_eq!(loaded.current_player(), State::Cross);
+        assert_eq!(loaded.active_board(), game.active_board());
+    }
+
+    #[test]
+    fn load_errors() {
+        assert!(matches!(
+            Game::from_save_str("placement free\n"),
+            Err(LoadError::MissingLayers)
+        ));
+        assert!(matches!(
+            Game::from_save_str("layers 1\nmoves 0 0\n"),
+            Err(LoadError::IllegalMove {
+                index: 1,
+                error: MoveError::Occupied
+            })
+        ));
+        assert!(matches!(
+            Game::from_save_str("layers 1\nmoves 0\nposition\n...\n...\n...\n"),
+            Err(LoadError::PositionMismatch)
+        ));
+        assert!(matches!(
+            Game::from_save_str("layers 1\nplacement everywhere\n"),
+            Err(LoadError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            Game::from_save_str("layers 15\n"),
+            Err(LoadError::TooLarge)
+        ));
+        assert!(matches!(
+            Game::from_save_str("layers 3\nboards 26\n"),
+            Err(LoadError::TooLarge)
+        ));
+        assert!(matches!(
+            Game::from_save_str("layers 2\nstart X3O4 O - 2\n"),
+            Err(LoadError::LayersMismatch {
+                layers: 2,
+                start_layers: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn save_and_load_from_start_position() {
+        let mut game = Game::from_notation("X3O4 O - 2", Rules::default()).unwrap();
+        game.play(Move {
+            path: "8".parse().unwrap(),
+        })
+        .unwrap();
+
+        let save = game.to_save_string(false);
+        assert!(save.contains("start X3O4 O - 2\nmoves 8\n"));
+        let loaded = Game::from_save_str(&save).unwrap();
+        assert_eq!(loaded.to_notation(), "X3O3O X - 3");
+    }
+}
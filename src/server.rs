@@ -0,0 +1,1009 @@
+//! A server hosting many games at once, for players and spectators connecting over TCP
+//!
+//! The protocol is line based, clients send:
+//!
+//! - `list` the server answers with a `lobby <id> players=<n> spectators=<n> status=<s> layers=<n> <rules>`
+//!   line for every lobby, followed by `end`
+//! - `create layers=<n> placement=<free|sent-to> [draws=<dead|both|majority>] [misere=<off|top|all>]
+//!   [boards=<size>:<in a row>,...]` creates a lobby and takes its circle seat, games with more layers or cells
+//!   than [`Rules::within_limits`] allows are refused
+//! - `join <id>` takes the free seat of a lobby, `spectate <id>` watches it
+//! - `reconnect <id> <token>` takes back a seat after losing the connection. A seat whose player has been gone for
+//!   longer than the reconnect timeout can be taken by anyone with `join`, and a lobby without anyone left in it
+//!   is removed after that time
+//! - `move <cell>` plays a move, `leave` leaves the lobby
+//!
+//! The server answers joining with `seat <id> side=<circle|cross> token=<token>`, spectating with `spectating <id>`,
+//! and then sends the whole game as `game <id> layers=<n> <rules> moves <cell> ...`, where the rules are written
+//! like when creating a lobby.
+//! After that everyone in the lobby gets `move <cell>` for every move, `player <side> <connected|disconnected>`
+//! when a player comes or goes and `gameover <circle|cross|draw>` at the end. Anything that isn't allowed is
+//! answered with `error <text>`. Every move is checked by the server before it is passed on, and a client sending a
+//! line longer than [`crate::MAX_LINE_LENGTH`] is disconnected
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{limited_lines, side_index, CellPath, Game, Move, Outcome, Rules, State};
+
+pub type LobbyId = u32;
+pub type ClientId = u64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientCommand {
+    List,
+    Create { num_layers: usize, rules: Rules },
+    Join(LobbyId),
+    Spectate(LobbyId),
+    Reconnect { lobby: LobbyId, token: u64 },
+    Move(Move),
+    Leave,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LobbyStatus {
+    Waiting,
+    Playing,
+    Over,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LobbyInfo {
+    pub id: LobbyId,
+    pub num_layers: usize,
+    pub rules: Rules,
+    pub players: usize,
+    pub spectators: usize,
+    pub status: LobbyStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerMessage {
+    Lobby(LobbyInfo),
+    EndOfList,
+    Seat {
+        lobby: LobbyId,
+        side: State,
+        token: u64,
+    },
+    Spectating(LobbyId),
+    Game {
+        lobby: LobbyId,
+        num_layers: usize,
+        rules: Rules,
+        moves: Vec<Move>,
+    },
+    Move(Move),
+    Player {
+        side: State,
+        connected: bool,
+    },
+    GameOver(Option<State>),
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseServerError(pub String);
+
+impl std::fmt::Display for ParseServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid message '{}'", self.0)
+    }
+}
+
+impl std::error::Error for ParseServerError {}
+
+impl std::fmt::Display for LobbyStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LobbyStatus::Waiting => write!(f, "waiting"),
+            LobbyStatus::Playing => write!(f, "playing"),
+            LobbyStatus::Over => write!(f, "over"),
+        }
+    }
+}
+
+impl std::fmt::Display for ClientCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientCommand::List => write!(f, "list"),
+            ClientCommand::Create { num_layers, rules } => {
+                write!(f, "create")?;
+                write_game_options(f, *num_layers, rules)
+            }
+            ClientCommand::Join(lobby) => write!(f, "join {lobby}"),
+            ClientCommand::Spectate(lobby) => write!(f, "spectate {lobby}"),
+            ClientCommand::Reconnect { lobby, token } => write!(f, "reconnect {lobby} {token:x}"),
+            ClientCommand::Move(mv) => write!(f, "move {}", mv.path),
+            ClientCommand::Leave => write!(f, "leave"),
+        }
+    }
+}
+
+fn write_game_options(
+    f: &mut std::fmt::Formatter<'_>,
+    num_layers: usize,
+    rules: &Rules,
+) -> std::fmt::Result {
+    write!(f, " layers={num_layers}")?;
+    for (name, value) in rules.options() {
+        write!(f, " {name}={value}")?;
+    }
+    Ok(())
+}
+
+/// Options of a message as key and value
+type Options<'a> = Vec<(&'a str, &'a str)>;
+
+/// Parses the number of layers and the rules shared by several messages, other options are returned.
+/// Games beyond [`Rules::within_limits`] are rejected
+fn parse_game_options<'a>(
+    options: impl Iterator<Item = &'a str>,
+) -> Option<(usize, Rules, Options<'a>)> {
+    let mut num_layers = None;
+    let mut rules = Rules::default();
+    let mut rest = vec![];
+    for option in options {
+        match option.split_once('=')? {
+            ("layers", layers) => num_layers = Some(layers.parse().ok().filter(|&n| n > 0)?),
+            (name, value) => {
+                if !rules.set_option(name, value).ok()? {
+                    rest.push((name, value));
+                }
+            }
+        }
+    }
+    let num_layers = num_layers.filter(|&num_layers| rules.within_limits(num_layers))?;
+    Some((num_layers, rules, rest))
+}
+
+fn parse_move(path: &str) -> Option<Move> {
+    Some(Move {
+        path: path.parse::<CellPath>().ok()?,
+    })
+}
+
+impl std::str::FromStr for ClientCommand {
+    type Err = ParseServerError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseServerError(line.to_string());
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let lobby = || {
+            words
+                .get(1)
+                .and_then(|id| id.parse().ok())
+                .ok_or_else(invalid)
+        };
+        Ok(match words.first().copied().ok_or_else(invalid)? {
+            "list" => ClientCommand::List,
+            "leave" => ClientCommand::Leave,
+            "join" => ClientCommand::Join(lobby()?),
+            "spectate" => ClientCommand::Spectate(lobby()?),
+            "reconnect" => ClientCommand::Reconnect {
+                lobby: lobby()?,
+                token: words
+                    .get(2)
+                    .and_then(|token| u64::from_str_radix(token, 16).ok())
+                    .ok_or_else(invalid)?,
+            },
+            "create" => {
+                let (num_layers, rules, _) =
+                    parse_game_options(words[1..].iter().copied()).ok_or_else(invalid)?;
+                ClientCommand::Create { num_layers, rules }
+            }
+            "move" => ClientCommand::Move(
+                words
+                    .get(1)
+                    .and_then(|&path| parse_move(path))
+                    .ok_or_else(invalid)?,
+            ),
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+impl std::fmt::Display for ServerMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerMessage::Lobby(info) => {
+                write!(
+                    f,
+                    "lobby {} players={} spectators={} status={}",
+                    info.id, info.players, info.spectators, info.status
+                )?;
+                write_game_options(f, info.num_layers, &info.rules)
+            }
+            ServerMessage::EndOfList => write!(f, "end"),
+            ServerMessage::Seat { lobby, side, token } => {
+                write!(f, "seat {lobby} side={} token={token:x}", side.name())
+            }
+            ServerMessage::Spectating(lobby) => write!(f, "spectating {lobby}"),
+            ServerMessage::Game {
+                lobby,
+                num_layers,
+                rules,
+                moves,
+            } => {
+                write!(f, "game {lobby}")?;
+                write_game_options(f, *num_layers, rules)?;
+                write!(f, " moves")?;
+                for mv in moves {
+                    write!(f, " {}", mv.path)?;
+                }
+                Ok(())
+            }
+            ServerMessage::Move(mv) => write!(f, "move {}", mv.path),
+            ServerMessage::Player { side, connected } => write!(
+                f,
+                "player {} {}",
+                side.name(),
+                if *connected {
+                    "connected"
+                } else {
+                    "disconnected"
+                }
+            ),
+            ServerMessage::GameOver(winner) => {
+                write!(f, "gameover {}", winner.map_or("draw", State::name))
+            }
+            ServerMessage::Error(error) => write!(f, "error {error}"),
+        }
+    }
+}
+
+impl std::str::FromStr for ServerMessage {
+    type Err = ParseServerError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseServerError(line.to_string());
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let lobby = || {
+            words
+                .get(1)
+                .and_then(|id| id.parse().ok())
+                .ok_or_else(invalid)
+        };
+        Ok(match words.first().copied().ok_or_else(invalid)? {
+            "lobby" => {
+                let (num_layers, rules, rest) =
+                    parse_game_options(words.iter().skip(2).copied()).ok_or_else(invalid)?;
+                let (mut players, mut spectators, mut status) = (None, None, None);
+                for (key, value) in rest {
+                    match key {
+                        "players" => players = value.parse().ok(),
+                        "spectators" => spectators = value.parse().ok(),
+                        "status" => {
+                            status = match value {
+                                "waiting" => Some(LobbyStatus::Waiting),
+                                "playing" => Some(LobbyStatus::Playing),
+                                "over" => Some(LobbyStatus::Over),
+                                _ => None,
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                ServerMessage::Lobby(LobbyInfo {
+                    id: lobby()?,
+                    num_layers,
+                    rules,
+                    players: players.ok_or_else(invalid)?,
+                    spectators: spectators.ok_or_else(invalid)?,
+                    status: status.ok_or_else(invalid)?,
+                })
+            }
+            "end" => ServerMessage::EndOfList,
+            "seat" => {
+                let (mut side, mut token) = (None, None);
+                for option in &words[2..] {
+                    match option.split_once('=').ok_or_else(invalid)? {
+                        ("side", name) => side = State::from_name(name),
+                        ("token", value) => token = u64::from_str_radix(value, 16).ok(),
+                        _ => {}
+                    }
+                }
+                ServerMessage::Seat {
+                    lobby: lobby()?,
+                    side: side.ok_or_else(invalid)?,
+                    token: token.ok_or_else(invalid)?,
+                }
+            }
+            "spectating" => ServerMessage::Spectating(lobby()?),
+            "game" => {
+                let moves_start = words
+                    .iter()
+                    .position(|&word| word == "moves")
+                    .ok_or_else(invalid)?;
+                let (num_layers, rules, _) =
+                    parse_game_options(words[2..moves_start].iter().copied())
+                        .ok_or_else(invalid)?;
+                ServerMessage::Game {
+                    lobby: lobby()?,
+                    num_layers,
+                    rules,
+                    moves: words[moves_start + 1..]
+                        .iter()
+                        .map(|&path| parse_move(path))
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?,
+                }
+            }
+            "move" => ServerMessage::Move(
+                words
+                    .get(1)
+                    .and_then(|&path| parse_move(path))
+                    .ok_or_else(invalid)?,
+            ),
+            "player" => ServerMessage::Player {
+                side: words
+                    .get(1)
+                    .and_then(|&name| State::from_name(name))
+                    .ok_or_else(invalid)?,
+                connected: match words.get(2) {
+                    Some(&"connected") => true,
+                    Some(&"disconnected") => false,
+                    _ => return Err(invalid()),
+                },
+            },
+            "gameover" => ServerMessage::GameOver(match words.get(1) {
+                Some(&"draw") => None,
+                Some(&name) => Some(State::from_name(name).ok_or_else(invalid)?),
+                None => return Err(invalid()),
+            }),
+            "error" => ServerMessage::Error(
+                line.trim()
+                    .strip_prefix("error")
+                    .unwrap_or_default()
+                    .trim_start()
+                    .to_string(),
+            ),
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Player(State),
+    Spectator,
+}
+
+struct Seat {
+    token: u64,
+    client: Option<ClientId>,
+    /// When the player of this seat left, while nobody is in it
+    left_at: Option<Instant>,
+}
+
+impl Seat {
+    /// Whether the player left longer than `timeout` ago, and can't expect to get the seat back anymore
+    fn is_abandoned(&self, timeout: Duration) -> bool {
+        self.left_at
+            .is_some_and(|left_at| left_at.elapsed() >= timeout)
+    }
+}
+
+struct Lobby {
+    game: Game,
+    /// The seats of circle and cross, `None` until someone has taken them
+    seats: [Option<Seat>; 2],
+    spectators: Vec<ClientId>,
+}
+
+impl Lobby {
+    fn seat(&self, side: State) -> &Option<Seat> {
+        &self.seats[side_index(side)]
+    }
+
+    fn seat_mut(&mut self, side: State) -> &mut Option<Seat> {
+        &mut self.seats[side_index(side)]
+    }
+
+    fn status(&self) -> LobbyStatus {
+        if self.game.status() != Outcome::Ongoing {
+            LobbyStatus::Over
+        } else if self.seats.iter().all(Option::is_some) {
+            LobbyStatus::Playing
+        } else {
+            LobbyStatus::Waiting
+        }
+    }
+
+    fn clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.seats
+            .iter()
+            .flatten()
+            .filter_map(|seat| seat.client)
+            .chain(self.spectators.iter().copied())
+    }
+}
+
+struct Client {
+    sender: mpsc::Sender<ServerMessage>,
+    lobby: Option<(LobbyId, Role)>,
+}
+
+/// The lobbies and clients of a server, independent of how the clients are connected
+pub struct ServerState {
+    clients: HashMap<ClientId, Client>,
+    lobbies: BTreeMap<LobbyId, Lobby>,
+    next_client: ClientId,
+    next_lobby: LobbyId,
+    reconnect_timeout: Duration,
+}
+
+impl Default for ServerState {
+    fn default() -> Self {
+        Self::with_reconnect_timeout(Self::RECONNECT_TIMEOUT)
+    }
+}
+
+impl ServerState {
+    /// How long a player who lost the connection keeps their seat
+    pub const RECONNECT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+    pub fn with_reconnect_timeout(reconnect_timeout: Duration) -> Self {
+        Self {
+            clients: HashMap::new(),
+            lobbies: BTreeMap::new(),
+            next_client: 0,
+            next_lobby: 0,
+            reconnect_timeout,
+        }
+    }
+
+    /// Adds a client, messages for it are sent to `sender`
+    pub fn connect(&mut self, sender: mpsc::Sender<ServerMessage>) -> ClientId {
+        let id = self.next_client;
+        self.next_client += 1;
+        self.clients.insert(
+            id,
+            Client {
+                sender,
+                lobby: None,
+            },
+        );
+        id
+    }
+
+    /// Removes a client, its seat is kept so it can reconnect
+    pub fn disconnect(&mut self, client: ClientId) {
+        self.leave(client);
+        self.clients.remove(&client);
+    }
+
+    fn send(&self, client: ClientId, message: ServerMessage) {
+        if let Some(client) = self.clients.get(&client) {
+            _ = client.sender.send(message);
+        }
+    }
+
+    fn broadcast(&self, lobby: LobbyId, message: ServerMessage) {
+        for client in self.lobbies[&lobby].clients() {
+            self.send(client, message.clone());
+        }
+    }
+
+    pub fn lobbies(&self) -> Vec<LobbyInfo> {
+        self.lobbies
+            .iter()
+            .map(|(&id, lobby)| LobbyInfo {
+                id,
+                num_layers: lobby.game.num_layers(),
+                rules: lobby.game.rules().clone(),
+                players: lobby.seats.iter().flatten().count(),
+                spectators: lobby.spectators.len(),
+                status: lobby.status(),
+            })
+            .collect()
+    }
+
+    pub fn handle(&mut self, client: ClientId, command: ClientCommand) {
+        self.remove_abandoned_lobbies();
+        if let Err(error) = self.try_handle(client, command) {
+            self.send(client, ServerMessage::Error(error));
+        }
+    }
+
+    fn try_handle(&mut self, client: ClientId, command: ClientCommand) -> Result<(), String> {
+        let current_lobby = self.clients.get(&client).ok_or("unknown client")?.lobby;
+        match command {
+            ClientCommand::List => {
+                for info in self.lobbies() {
+                    self.send(client, ServerMessage::Lobby(info));
+                }
+                self.send(client, ServerMessage::EndOfList);
+            }
+            ClientCommand::Create { num_layers, rules } => {
+                self.try_create(client, Game::new(num_layers, rules))?
+            }
+            ClientCommand::Join(id) => {
+                if current_lobby.is_some() {
+                    return Err("leave the current lobby first".into());
+                }
+                let lobby = self.lobbies.get(&id).ok_or("no such lobby")?;
+                let side = [State::Circle, State::Cross]
+                    .into_iter()
+                    .find(|&side| {
+                        lobby.seat(side).as_ref().is_none_or(|seat| {
+                            seat.client.is_none() && seat.is_abandoned(self.reconnect_timeout)
+                        })
+                    })
+                    .ok_or("the lobby is full")?;
+                self.take_seat(client, id, side, rand::random());
+            }
+            ClientCommand::Reconnect { lobby: id, token } => {
+                if current_lobby.is_some() {
+                    return Err("leave the current lobby first".into());
+                }
+                let lobby = self.lobbies.get(&id).ok_or("no such lobby")?;
+                let side = [State::Circle, State::Cross]
+                    .into_iter()
+                    .find(|&side| {
+                        lobby
+                            .seat(side)
+                            .as_ref()
+                            .is_some_and(|seat| seat.token == token && seat.client.is_none())
+                    })
+                    .ok_or("no free seat with this token")?;
+                self.take_seat(client, id, side, token);
+            }
+            ClientCommand::Spectate(id) => {
+                if current_lobby.is_some() {
+                    return Err("leave the current lobby first".into());
+                }
+                let lobby = self.lobbies.get_mut(&id).ok_or("no such lobby")?;
+                lobby.spectators.push(client);
+                self.clients.get_mut(&client).unwrap().lobby = Some((id, Role::Spectator));
+                self.send(client, ServerMessage::Spectating(id));
+                self.send_game(client, id);
+            }
+            ClientCommand::Move(mv) => {
+                let Some((id, Role::Player(side))) = current_lobby else {
+                    return Err("only players can move".into());
+                };
+                let lobby = self.lobbies.get_mut(&id).unwrap();
+                if lobby.status() == LobbyStatus::Waiting {
+                    return Err("waiting for an opponent".into());
+                }
+                if lobby.game.current_player() != side {
+                    return Err("it is not your turn".into());
+                }
+                let outcome = lobby
+                    .game
+                    .play(mv.clone())
+                    .map_err(|error| error.to_string())?;
+                self.broadcast(id, ServerMessage::Move(mv));
+                match outcome {
+                    Outcome::Ongoing => {}
+                    Outcome::Won(winner) => {
+                        self.broadcast(id, ServerMessage::GameOver(Some(winner)))
+                    }
+                    Outcome::Stalemate => self.broadcast(id, ServerMessage::GameOver(None)),
+                }
+            }
+            ClientCommand::Leave => {
+                if current_lobby.is_none() {
+                    return Err("not in a lobby".into());
+                }
+                self.leave(client);
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a lobby for a game that was built beforehand, like [`ServerState::handle`] does for
+    /// [`ClientCommand::Create`]. Building a big game takes a while, so it shouldn't be done while holding on
+    /// to the state
+    pub fn create(&mut self, client: ClientId, game: Game) {
+        self.remove_abandoned_lobbies();
+        if let Err(error) = self.try_create(client, game) {
+            self.send(client, ServerMessage::Error(error));
+        }
+    }
+
+    fn try_create(&mut self, client: ClientId, game: Game) -> Result<(), String> {
+        if self
+            .clients
+            .get(&client)
+            .ok_or("unknown client")?
+            .lobby
+            .is_some()
+        {
+            return Err("leave the current lobby first".into());
+        }
+        let id = self.next_lobby;
+        self.next_lobby += 1;
+        self.lobbies.insert(
+            id,
+            Lobby {
+                game,
+                seats: [None, None],
+                spectators: vec![],
+            },
+        );
+        self.take_seat(client, id, State::Circle, rand::random());
+        Ok(())
+    }
+
+    fn take_seat(&mut self, client: ClientId, id: LobbyId, side: State, token: u64) {
+        let lobby = self.lobbies.get_mut(&id).unwrap();
+        *lobby.seat_mut(side) = Some(Seat {
+            token,
+            client: Some(client),
+            left_at: None,
+        });
+        self.clients.get_mut(&client).unwrap().lobby = Some((id, Role::Player(side)));
+        self.send(
+            client,
+            ServerMessage::Seat {
+                lobby: id,
+                side,
+                token,
+            },
+        );
+        self.send_game(client, id);
+        self.broadcast(
+            id,
+            ServerMessage::Player {
+                side,
+                connected: true,
+            },
+        );
+    }
+
+    /// Sends the whole game, for clients that just joined or reconnected
+    fn send_game(&self, client: ClientId, id: LobbyId) {
+        let game = &self.lobbies[&id].game;
+        self.send(
+            client,
+            ServerMessage::Game {
+                lobby: id,
+                num_layers: game.num_layers(),
+                rules: game.rules().clone(),
+                moves: game.moves().cloned().collect(),
+            },
+        );
+        match game.status() {
+            Outcome::Ongoing => {}
+            Outcome::Won(winner) => self.send(client, ServerMessage::GameOver(Some(winner))),
+            Outcome::Stalemate => self.send(client, ServerMessage::GameOver(None)),
+        }
+    }
+
+    fn leave(&mut self, client: ClientId) {
+        let Some((id, role)) = self
+            .clients
+            .get_mut(&client)
+            .and_then(|client| client.lobby.take())
+        else {
+            return;
+        };
+        let lobby = self.lobbies.get_mut(&id).unwrap();
+        match role {
+            Role::Player(side) => {
+                if let Some(seat) = lobby.seat_mut(side) {
+                    seat.client = None;
+                    seat.left_at = Some(Instant::now());
+                }
+                self.broadcast(
+                    id,
+                    ServerMessage::Player {
+                        side,
+                        connected: false,
+                    },
+                );
+            }
+            Role::Spectator => lobby.spectators.retain(|&spectator| spectator != client),
+        }
+
+        // lobbies are kept for reconnecting, unless there is nothing left to come back to
+        let lobby = &self.lobbies[&id];
+        if lobby.clients().next().is_none() && lobby.status() != LobbyStatus::Playing {
+            self.lobbies.remove(&id);
+        }
+    }
+
+    /// Removes the games nobody has come back to within the reconnect timeout
+    pub fn remove_abandoned_lobbies(&mut self) {
+        let timeout = self.reconnect_timeout;
+        self.lobbies.retain(|_, lobby| {
+            lobby.clients().next().is_some()
+                || !lobby
+                    .seats
+                    .iter()
+                    .flatten()
+                    .all(|seat| seat.is_abandoned(timeout))
+        });
+    }
+}
+
+/// Accepts clients on `listener` forever, every client is handled on its own threads
+pub fn serve(listener: TcpListener) -> std::io::Result<()> {
+    let state = Arc::new(Mutex::new(ServerState::default()));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let state = state.clone();
+        std::thread::spawn(move || {
+            _ = handle_client(stream, &state);
+        });
+    }
+    Ok(())
+}
+
+fn handle_client(stream: TcpStream, state: &Mutex<ServerState>) -> std::io::Result<()> {
+    let (sender, receiver) = mpsc::channel::<ServerMessage>();
+    let mut writer = stream.try_clone()?;
+    // a separate writer, so a slow client doesn't hold up everyone else
+    std::thread::spawn(move || {
+        for message in receiver {
+            if writeln!(writer, "{message}").is_err() {
+                break;
+            }
+        }
+    });
+
+    let client = state.lock().unwrap().connect(sender.clone());
+    let result = limited_lines(BufReader::new(stream)).try_for_each(|line| {
+        let line = line?;
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        match line.parse() {
+            Ok(ClientCommand::Create { num_layers, rules }) => {
+                let game = Game::new(num_layers, rules);
+                state.lock().unwrap().create(client, game);
+            }
+            Ok(command) => state.lock().unwrap().handle(client, command),
+            Err(error) => _ = sender.send(ServerMessage::Error(format!("{error}"))),
+        }
+        Ok(())
+    });
+    state.lock().unwrap().disconnect(client);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mv(path: &str) -> Move {
+        Move {
+            path: path.parse().unwrap(),
+        }
+    }
+
+    fn client(state: &mut ServerState) -> (ClientId, mpsc::Receiver<ServerMessage>) {
+        let (sender, receiver) = mpsc::channel();
+        (state.connect(sender), receiver)
+    }
+
+    fn received(receiver: &mpsc::Receiver<ServerMessage>) -> Vec<String> {
+        receiver
+            .try_iter()
+            .map(|message| message.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        for line in [
+            "list",
+            "create layers=3 placement=sent-to draws=both",
+            "join 2",
+            "spectate 0",
+            "reconnect 1 ff00",
+            "move 4.0",
+            "leave",
+        ] {
+            assert_eq!(line.parse::<ClientCommand>().unwrap().to_string(), line);
+        }
+        for line in [
+            "lobby 3 players=1 spectators=0 status=waiting layers=2 placement=free",
+            "end",
+            "seat 3 side=cross token=1a2b",
+            "spectating 3",
+            "game 3 layers=2 placement=free moves 4.0 0.8",
+            "move 0.8",
+            "player circle disconnected",
+            "gameover draw",
+            "error it is not your turn",
+        ] {
+            assert_eq!(line.parse::<ServerMessage>().unwrap().to_string(), line);
+        }
+    }
+
+    #[test]
+    fn oversized_games_are_rejected() {
+        for line in [
+            "create layers=12",
+            "create layers=6 placement=free",
+            "create layers=3 placement=free boards=26",
+            "create layers=2 placement=free boards=16:3",
+        ] {
+            assert!(line.parse::<ClientCommand>().is_err(), "{line}");
+        }
+        for line in [
+            "create layers=5 placement=free",
+            "create layers=2 placement=free boards=15:3",
+        ] {
+            assert!(line.parse::<ClientCommand>().is_ok(), "{line}");
+        }
+        assert!("game 0 layers=9 placement=free moves"
+            .parse::<ServerMessage>()
+            .is_err());
+    }
+
+    #[test]
+    fn lobbies_players_and_spectators() {
+        let mut state = ServerState::default();
+        let (circle, circle_messages) = client(&mut state);
+        let (cross, cross_messages) = client(&mut state);
+        let (spectator, spectator_messages) = client(&mut state);
+
+        state.handle(circle, "create layers=1 placement=free".parse().unwrap());
+        state.handle(circle, ClientCommand::Move(mv("4")));
+        let messages = received(&circle_messages);
+        assert!(messages[0].starts_with("seat 0 side=circle"));
+        assert_eq!(
+            messages[1..],
+            [
+                "game 0 layers=1 placement=free moves",
+                "player circle connected",
+                "error waiting for an opponent"
+            ]
+        );
+
+        state.handle(cross, ClientCommand::Join(0));
+        state.handle(spectator, ClientCommand::Spectate(0));
+        assert_eq!(state.lobbies()[0].status, LobbyStatus::Playing);
+        assert_eq!(state.lobbies()[0].spectators, 1);
+        state.handle(cross, ClientCommand::Move(mv("4")));
+        for path in ["4", "0", "1", "8", "7"] {
+            let player = if state.lobbies[&0].game.current_player() == State::Circle {
+                circle
+            } else {
+                cross
+            };
+            state.handle(player, ClientCommand::Move(mv(path)));
+        }
+        state.handle(cross, ClientCommand::Move(mv("2")));
+
+        let messages = received(&spectator_messages);
+        assert_eq!(
+            messages,
+            [
+                "spectating 0",
+                "game 0 layers=1 placement=free moves",
+                "move 4",
+                "move 0",
+                "move 1",
+                "move 8",
+                "move 7",
+                "gameover circle",
+            ]
+        );
+        let messages = received(&cross_messages);
+        assert!(messages.contains(&"error it is not your turn".to_string()));
+        assert!(messages.contains(&"error the game is already over".to_string()));
+    }
+
+    #[test]
+    fn reconnecting_resends_the_game() {
+        let mut state = ServerState::default();
+        let (circle, circle_messages) = client(&mut state);
+        let (cross, _cross_messages) = client(&mut state);
+        state.handle(circle, "create layers=2 placement=sent-to".parse().unwrap());
+        state.handle(cross, ClientCommand::Join(0));
+        state.handle(circle, ClientCommand::Move(mv("4.0")));
+        let Some(ServerMessage::Seat { token, .. }) = circle_messages.try_iter().next() else {
+            panic!("no seat");
+        };
+
+        state.disconnect(circle);
+        assert_eq!(state.lobbies()[0].status, LobbyStatus::Playing);
+        let (circle, circle_messages) = client(&mut state);
+        state.handle(
+            circle,
+            ClientCommand::Reconnect {
+                lobby: 0,
+                token: token + 1,
+            },
+        );
+        state.handle(circle, ClientCommand::Reconnect { lobby: 0, token });
+        let messages = received(&circle_messages);
+        assert_eq!(messages[0], "error no free seat with this token");
+        assert_eq!(messages[2], "game 0 layers=2 placement=sent-to moves 4.0");
+
+        // the cross player leaving doesn't make the lobby disappear either
+        state.handle(cross, ClientCommand::Leave);
+        assert_eq!(state.lobbies().len(), 1);
+    }
+
+    #[test]
+    fn abandoned_games_expire() {
+        let mut state = ServerState::with_reconnect_timeout(Duration::ZERO);
+        let (circle, _circle_messages) = client(&mut state);
+        let (cross, _cross_messages) = client(&mut state);
+        state.handle(circle, "create layers=1 placement=free".parse().unwrap());
+        state.handle(cross, ClientCommand::Join(0));
+        state.handle(circle, ClientCommand::Move(mv("4")));
+
+        // someone else can take the seat of a player who is gone for good
+        state.disconnect(circle);
+        let (newcomer, newcomer_messages) = client(&mut state);
+        state.handle(newcomer, ClientCommand::Join(0));
+        assert!(received(&newcomer_messages)[0].starts_with("seat 0 side=circle"));
+
+        state.disconnect(newcomer);
+        state.disconnect(cross);
+        assert_eq!(state.lobbies()[0].status, LobbyStatus::Playing);
+        state.remove_abandoned_lobbies();
+        assert!(state.lobbies().is_empty());
+
+        // with the usual timeout, the seat stays reserved
+        let mut state = ServerState::default();
+        let (circle, _circle_messages) = client(&mut state);
+        let (cross, _cross_messages) = client(&mut state);
+        state.handle(circle, "create layers=1 placement=free".parse().unwrap());
+        state.handle(cross, ClientCommand::Join(0));
+        state.disconnect(circle);
+        state.disconnect(cross);
+        let (newcomer, newcomer_messages) = client(&mut state);
+        state.handle(newcomer, ClientCommand::Join(0));
+        assert_eq!(received(&newcomer_messages), ["error the lobby is full"]);
+        assert_eq!(state.lobbies().len(), 1);
+    }
+
+    #[test]
+    fn serve_on_localhost() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(listener));
+
+        let stream = TcpStream::connect(address).unwrap();
+        let mut lines = limited_lines(BufReader::new(stream.try_clone().unwrap()));
+        let mut stream = stream;
+        writeln!(stream, "create layers=2 placement=free").unwrap();
+        writeln!(stream, "list").unwrap();
+        let mut next = || lines.next().unwrap().unwrap();
+        assert!(next().starts_with("seat 0 side=circle token="));
+        assert_eq!(next(), "game 0 layers=2 placement=free moves");
+        assert_eq!(next(), "player circle connected");
+        assert_eq!(
+            next(),
+            "lobby 0 players=1 spectators=0 status=waiting layers=2 placement=free"
+        );
+        assert_eq!(next(), "end");
+    }
+
+    #[test]
+    fn endless_lines_drop_the_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(listener));
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let line = "x".repeat(crate::MAX_LINE_LENGTH + 1);
+        // the server may stop reading before all of it has been sent
+        _ = stream.write_all(line.as_bytes());
+        let mut buffer = [0; 16];
+        match std::io::Read::read(&mut stream, &mut buffer) {
+            Ok(read) => assert_eq!(read, 0),
+            // a reset connection is closed as well, only running into the timeout isn't
+            Err(error) => assert!(!matches!(
+                error.kind(),
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            )),
+        }
+    }
+}
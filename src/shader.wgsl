@@ -5,8 +5,9 @@ struct VertexInput {
     @location(3) color: vec3<f32>,
     @location(4) is_circle: u32,
     @location(5) circle_width: f32,
-    @location(6) position: vec2<f32>,
-    @location(7) tex_coord: vec2<f32>,
+    @location(6) alpha: f32,
+    @location(7) position: vec2<f32>,
+    @location(8) tex_coord: vec2<f32>,
 };
 
 struct VertexOutput {
@@ -15,7 +16,7 @@ struct VertexOutput {
     @interpolate(flat) @location(1) circle_width: f32,
     @location(2) position: vec2<f32>,
     @location(3) tex_coord: vec2<f32>,
-    @location(4) color: vec3<f32>,
+    @location(4) color: vec4<f32>,
 };
 
 struct Camera {
@@ -52,7 +53,7 @@ fn vs_main(
         out.clip_position.w,
     );
     out.tex_coord = model.tex_coord;
-    out.color = model.color;
+    out.color = vec4<f32>(model.color, model.alpha);
     return out;
 }
 
@@ -64,5 +65,5 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
         discard;
     }
 
-    return vec4<f32>(in.color, 1.0);
+    return in.color;
 }
@@ -0,0 +1,113 @@
+use crate::{Board, State};
+
+impl Board {
+    /// Draws the board as text, with the boards of each layer separated by more and more lines
+    ///
+    /// Rows are drawn from the top, so the bottom left cell is cell `0`
+    pub fn to_text(&self) -> String {
+        let grid = self.leaf_grid();
+
+        // the number of cells across a board of each layer below the outermost one, from the innermost layer up
+        let block_sizes = self.layer_sizes()[1..]
+            .iter()
+            .rev()
+            .scan(1, |size, &layer_size| {
+                *size *= layer_size;
+                Some(*size)
+            })
+            .collect::<Vec<_>>();
+        // how many layers of boards end after this column or row
+        let boundary_level = |i: usize| {
+            block_sizes
+                .iter()
+                .take_while(|&&size| (i + 1).is_multiple_of(size))
+                .count()
+        };
+
+        let rows = grid
+            .iter()
+            .map(|row| {
+                let mut line = String::new();
+                for (x, cell) in row.iter().enumerate() {
+                    line.push(match cell {
+                        None => '.',
+                        Some(State::Circle) => 'O',
+                        Some(State::Cross) => 'X',
+                    });
+                    if x + 1 < row.len() {
+                        line.push(' ');
+                        let level = boundary_level(x);
+                        if level > 0 {
+                            line.push_str(&"|".repeat(level));
+                            line.push(' ');
+                        }
+                    }
+                }
+                line
+            })
+            .collect::<Vec<_>>();
+
+        let mut text = String::new();
+        for (y, row) in rows.iter().enumerate() {
+            text.push_str(row);
+            text.push('\n');
+            let level = boundary_level(y);
+            if level > 0 && y + 1 < rows.len() {
+                let fill = match level {
+                    1 => '-',
+                    2 => '=',
+                    _ => '#',
+                };
+                text.extend(row.chars().map(|c| if c == '|' { '+' } else { fill }));
+                text.push('\n');
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BoardShape;
+
+    #[test]
+    fn other_board_shapes() {
+        let shapes = [
+            BoardShape {
+                size: 2,
+                win_length: 2,
+            },
+            BoardShape {
+                size: 4,
+                win_length: 3,
+            },
+        ];
+        let board = Board::with_shapes(&shapes);
+        let text = board.to_text();
+        assert_eq!(text.lines().count(), 9);
+        assert_eq!(text.lines().next(), Some(". . . . | . . . ."));
+        assert_eq!(text.lines().nth(4), Some("--------+--------"));
+    }
+
+    #[test]
+    fn two_layers() {
+        let board: Board = "[O8]5[6X2]2".parse().unwrap();
+        assert_eq!(
+            board.to_text(),
+            "\
+X . . | . . . | . . .
+. . . | . . . | . . .
+. . . | . . . | . . .
+------+-------+------
+. . . | . . . | . . .
+. . . | . . . | . . .
+. . . | . . . | . . .
+------+-------+------
+. . . | . . . | . . .
+. . . | . . . | . . .
+O . . | . . . | . . .
+"
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{Bot, Game, Outcome, State};
+
+/// Plays `game` to the end, `bots` are the players for circle and cross
+///
+/// A bot that doesn't play a legal move loses the game. Returns the winner, `None` for a draw
+pub fn play_game(game: &mut Game, bots: [&mut dyn Bot; 2]) -> Option<State> {
+    let [circle, cross] = bots;
+    loop {
+        match game.status() {
+            Outcome::Won(winner) => return Some(winner),
+            Outcome::Stalemate => return None,
+            Outcome::Ongoing => {}
+        }
+        let bot = match game.current_player() {
+            State::Circle => &mut *circle,
+            State::Cross => &mut *cross,
+        };
+        let played = bot.choose_move(game).map(|mv| game.play(mv));
+        if !matches!(played, Some(Ok(_))) {
+            return Some(game.current_player().opponent());
+        }
+    }
+}
+
+/// The results of a match between two players, from the point of view of the first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MatchResults {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchResults {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Adds the result of a game in which the first player played as `side`
+    pub fn add(&mut self, winner: Option<State>, side: State) {
+        match winner {
+            None => self.draws += 1,
+            Some(winner) if winner == side => self.wins += 1,
+            Some(_) => self.losses += 1,
+        }
+    }
+
+    /// The average points per game, with a win worth 1 and a draw worth half
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    /// How many Elo points stronger the first player is, infinite if one of the players won every game
+    pub fn elo_difference(&self) -> f64 {
+        elo_from_score(self.score())
+    }
+
+    /// The 95% confidence interval of the Elo difference
+    pub fn elo_confidence_interval(&self) -> (f64, f64) {
+        let games = self.games() as f64;
+        let score = self.score();
+        let variance = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / games;
+        let margin = 1.96 * (variance / games).sqrt();
+        (
+            elo_from_score((score - margin).max(0.0)),
+            elo_from_score((score + margin).min(1.0)),
+        )
+    }
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+impl std::fmt::Display for MatchResults {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} wins, {} draws, {} losses",
+            self.wins, self.draws, self.losses
+        )?;
+        if self.games() > 0 {
+            let (low, high) = self.elo_confidence_interval();
+            write!(
+                f,
+                "\nscore {:.1}%, Elo difference {:+.0} (95% confidence {:+.0} to {:+.0})",
+                self.score() * 100.0,
+                self.elo_difference(),
+                low,
+                high
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GreedyBot, RandomBot, Rules};
+
+    #[test]
+    fn elo_difference() {
+        let even = MatchResults {
+            wins: 10,
+            draws: 5,
+            losses: 10,
+        };
+        assert_eq!(even.elo_difference(), 0.0);
+        let (low, high) = even.elo_confidence_interval();
+        assert!(low < 0.0 && high > 0.0 && (low + high).abs() < 1e-9);
+
+        let better = MatchResults {
+            wins: 6,
+            draws: 3,
+            losses: 1,
+        };
+        assert!((better.elo_difference() - 190.85).abs() < 0.01);
+        let (low, high) = better.elo_confidence_interval();
+        assert!(low < 190.85 && high > 190.85);
+
+        let perfect = MatchResults {
+            wins: 3,
+            draws: 0,
+            losses: 0,
+        };
+        assert_eq!(perfect.elo_difference(), f64::INFINITY);
+    }
+
+    #[test]
+    fn greedy_beats_random() {
+        let mut results = MatchResults::default();
+        let mut greedy = GreedyBot::new(1);
+        let mut random = RandomBot::new(2);
+        for i in 0..20 {
+            let mut game = Game::new(2, Rules::default());
+            let winner = if i % 2 == 0 {
+                play_game(&mut game, [&mut greedy, &mut random])
+            } else {
+                play_game(&mut game, [&mut random, &mut greedy])
+            };
+            let side = [State::Circle, State::Cross][i % 2];
+            results.add(winner, side);
+        }
+        assert_eq!(results.games(), 20);
+        assert!(results.wins > results.losses);
+    }
+}
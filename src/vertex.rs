@@ -16,12 +16,12 @@ impl Vertex {
     pub const ATTRIBUTES: &[wgpu::VertexAttribute] = &[
         wgpu::VertexAttribute {
             offset: offset_of!(Self, position) as wgpu::BufferAddress,
-            shader_location: 6,
+            shader_location: 7,
             format: wgpu::VertexFormat::Float32x2,
         },
         wgpu::VertexAttribute {
             offset: offset_of!(Self, tex_coord) as wgpu::BufferAddress,
-            shader_location: 7,
+            shader_location: 8,
             format: wgpu::VertexFormat::Float32x2,
         },
     ];
@@ -0,0 +1,220 @@
+//! Zobrist hashing of positions, and a transposition table keyed by the hashes
+//!
+//! The hash of a position is the xor of a key for every owned cell on every layer, one for every won sub-board,
+//! one for the board that has to be played in and one for cross being the side to move. Keys are derived from the
+//! path of the cell with splitmix64 instead of being drawn from a table, so they don't depend on the size of the
+//! board and are the same in every run and on every machine, which makes hashes safe to store
+
+use crate::{Board, Element, State};
+
+/// The key that is part of the hash whenever cross is the side to move
+pub const CROSS_TO_MOVE_KEY: u64 = splitmix64(6);
+
+const fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn path_key(kind: u64, path: &[(usize, usize)]) -> u64 {
+    path.iter().fold(splitmix64(kind), |key, &(x, y)| {
+        splitmix64(key ^ ((x as u64) << 32 | y as u64))
+    })
+}
+
+/// The key of a cell owned by `state`
+pub fn cell_key(path: &[(usize, usize)], state: State) -> u64 {
+    match state {
+        State::Circle => path_key(1, path),
+        State::Cross => path_key(2, path),
+    }
+}
+
+/// The key of a sub-board that has been won by `winner` and collapsed, on top of the keys of its cells
+pub fn won_key(path: &[(usize, usize)], winner: State) -> u64 {
+    match winner {
+        State::Circle => path_key(3, path),
+        State::Cross => path_key(4, path),
+    }
+}
+
+/// The key of the board that has to be played in, being able to play anywhere has no key
+pub fn active_board_key(path: &[(usize, usize)]) -> u64 {
+    if path.is_empty() {
+        0
+    } else {
+        path_key(5, path)
+    }
+}
+
+impl Board {
+    /// The hash of the cells and won sub-boards of this board, computed from scratch
+    pub fn zobrist_hash(&self) -> u64 {
+        fn hash(board: &Board, path: &mut Vec<(usize, usize)>) -> u64 {
+            let mut key = 0;
+            for (x, column) in board.elements.iter().enumerate() {
+                for (y, element) in column.iter().enumerate() {
+                    path.push((x, y));
+                    key ^= match element {
+                        Element::State(None) => 0,
+                        &Element::State(Some(state)) => cell_key(path, state),
+                        Element::Board(board) => hash(board, path),
+                        &Element::Won { winner, ref board } => {
+                            won_key(path, winner) ^ hash(board, path)
+                        }
+                    };
+                    path.pop();
+                }
+            }
+            key
+        }
+
+        hash(self, &mut vec![])
+    }
+}
+
+/// A cache of values for positions, holding at most a fixed number of them
+///
+/// Every hash has a single slot it can be stored in, a new value replaces whatever was in its slot before
+#[derive(Debug, Clone)]
+pub struct TranspositionTable<T> {
+    entries: Vec<Option<(u64, T)>>,
+}
+
+impl<T> TranspositionTable<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+        Self {
+            entries: std::iter::repeat_with(|| None).take(capacity).collect(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn slot(&self, hash: u64) -> usize {
+        (hash % self.entries.len() as u64) as usize
+    }
+
+    pub fn get(&self, hash: u64) -> Option<&T> {
+        match &self.entries[self.slot(hash)] {
+            Some((stored, value)) if *stored == hash => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, hash: u64) -> Option<&mut T> {
+        let slot = self.slot(hash);
+        match &mut self.entries[slot] {
+            Some((stored, value)) if *stored == hash => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Stores `value` for `hash`, returning the value it replaced if that was for the same hash
+    pub fn insert(&mut self, hash: u64, value: T) -> Option<T> {
+        let slot = self.slot(hash);
+        match self.entries[slot].replace((hash, value)) {
+            Some((stored, value)) if stored == hash => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::{Game, Placement, Rules};
+
+    #[test]
+    fn keys_are_stable() {
+        assert_eq!(Board::new(2).zobrist_hash(), 0);
+        assert_ne!(
+            cell_key(&[(1, 1)], State::Circle),
+            cell_key(&[(1, 1)], State::Cross)
+        );
+        assert_ne!(
+            cell_key(&[(0, 1)], State::Circle),
+            cell_key(&[(1, 0)], State::Circle)
+        );
+        assert_ne!(
+            cell_key(&[(1, 1)], State::Circle),
+            won_key(&[(1, 1)], State::Circle)
+        );
+        // the keys must never change, or stored hashes would stop matching
+        assert_eq!(splitmix64(0), 0xe220_a839_7b1d_cdaf);
+    }
+
+    #[test]
+    fn incremental_hash_matches_full_hash() {
+        let rules = Rules {
+            placement: Placement::SentTo,
+            ..Default::default()
+        };
+        let full_hash = |game: &Game| {
+            let side = match game.current_player() {
+                State::Circle => 0,
+                State::Cross => CROSS_TO_MOVE_KEY,
+            };
+            game.board().zobrist_hash() ^ side ^ active_board_key(game.active_board())
+        };
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let mut game = Game::new(2, rules);
+        let mut hashes = vec![game.zobrist_hash()];
+        while let Some(mv) = game.legal_moves().choose(&mut rng).cloned() {
+            game.play(mv).unwrap();
+            assert_eq!(game.zobrist_hash(), full_hash(&game));
+            hashes.push(game.zobrist_hash());
+        }
+        let end = game.to_notation();
+        while game.undo().is_some() {
+            hashes.pop();
+            assert_eq!(game.zobrist_hash(), full_hash(&game));
+            assert_eq!(Some(&game.zobrist_hash()), hashes.last());
+        }
+
+        let loaded = Game::from_notation(&end, Rules::default()).unwrap();
+        assert_eq!(loaded.zobrist_hash(), full_hash(&loaded));
+    }
+
+    #[test]
+    fn transpositions_have_the_same_hash() {
+        let mut first = Game::new(2, Rules::default());
+        let mut second = first.clone();
+        for path in ["0.0", "4.4", "8.8"] {
+            first
+                .play(crate::Move {
+                    path: path.parse().unwrap(),
+                })
+                .unwrap();
+        }
+        for path in ["8.8", "4.4", "0.0"] {
+            second
+                .play(crate::Move {
+                    path: path.parse().unwrap(),
+                })
+                .unwrap();
+        }
+        assert_eq!(first.zobrist_hash(), second.zobrist_hash());
+
+        let mut table = TranspositionTable::new(16);
+        assert_eq!(table.insert(first.zobrist_hash(), 1), None);
+        assert_eq!(table.get(second.zobrist_hash()), Some(&1));
+        assert_eq!(table.insert(second.zobrist_hash(), 2), Some(1));
+        assert_eq!(
+            table.get(Game::new(2, Rules::default()).zobrist_hash()),
+            None
+        );
+        table.clear();
+        assert_eq!(table.get(first.zobrist_hash()), None);
+    }
+}